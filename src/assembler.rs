@@ -0,0 +1,971 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{consts::XO_MEMORY_SIZE, instruction::Instruction};
+
+/// Address programs are loaded at.
+const ORIGIN: u16 = 0x200;
+
+/// Guards against macros that (indirectly) expand themselves forever.
+const MAX_EXPANSIONS: usize = 100_000;
+
+/**
+ * An assembled program.
+ *
+ * `rom` starts at 0x200 and can be passed straight to `Cpu::load_rom_bytes`.
+ * If the source has a `main` label, the first instruction is a jump to it,
+ * like in Octo.
+ */
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    pub breakpoints: Vec<(String, u16)>, // From `:breakpoint name`
+}
+
+impl Program {
+    /// Labels sorted by address, one `0xNNNN name` per line.
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<_> = self.labels.iter().collect();
+        symbols.sort_by_key(|(name, addr)| (**addr, (*name).clone()));
+
+        symbols
+            .iter()
+            .map(|(name, addr)| format!("0x{:04X} {}\n", addr, name))
+            .collect()
+    }
+}
+
+/// Assembles Octo source into a ROM. Errors carry the source line number.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    Assembler::new(source).run()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Where an unresolved name has to be patched in once it is defined.
+#[derive(Clone, Copy)]
+enum Patch {
+    Addr12, // Low 12 bits of the opcode
+    Long,   // Second word of F000 NNNN
+    Unpack, // Low nibble of `v0 := NN` and all of `v1 := NN`
+}
+
+impl Patch {
+    /// First address that doesn't fit.
+    fn limit(self) -> usize {
+        match self {
+            Patch::Long => XO_MEMORY_SIZE,
+            Patch::Addr12 | Patch::Unpack => 0x1000,
+        }
+    }
+}
+
+struct Fixup {
+    addr: u16,
+    patch: Patch,
+    name: String,
+    line: usize,
+}
+
+/// Open `if ... begin` or `loop` blocks.
+enum Block {
+    If { jump: u16 },
+    Else { jump: u16 },
+    Loop { start: u16, exits: Vec<u16> },
+}
+
+/// Comparisons allowed in `if`/`while` conditions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Key,
+    NotKey,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Cmp {
+    fn negate(self) -> Cmp {
+        match self {
+            Cmp::Eq => Cmp::Ne,
+            Cmp::Ne => Cmp::Eq,
+            Cmp::Key => Cmp::NotKey,
+            Cmp::NotKey => Cmp::Key,
+            Cmp::Lt => Cmp::Ge,
+            Cmp::Ge => Cmp::Lt,
+            Cmp::Gt => Cmp::Le,
+            Cmp::Le => Cmp::Gt,
+        }
+    }
+}
+
+/// Right-hand side of an assignment or comparison.
+enum Operand {
+    Reg(usize),
+    Imm(u8),
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    memory: Vec<u8>,
+    here: u16,
+    end: u16, // One past the highest address written
+    labels: BTreeMap<String, u16>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    breakpoints: Vec<(String, u16)>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        Assembler {
+            tokens: tokenize(source),
+            line: 0,
+            memory: vec![0; XO_MEMORY_SIZE],
+            here: ORIGIN,
+            end: ORIGIN,
+            labels: BTreeMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            breakpoints: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(mut self) -> Result<Program, String> {
+        let has_main = self
+            .tokens
+            .iter()
+            .zip(self.tokens.iter().skip(1))
+            .any(|(colon, name)| colon.text == ":" && name.text == "main");
+
+        // Like Octo, start with a jump to `main` when there is one
+        if has_main {
+            self.fixup(Patch::Addr12, "main".to_string());
+            self.emit(Instruction::Jump { addr: 0 })?;
+        }
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(token.text)
+                .map_err(|e| format!("line {}: {}", self.line, e))?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let open = match block {
+                Block::If { .. } | Block::Else { .. } => "begin without end",
+                Block::Loop { .. } => "loop without again",
+            };
+            return Err(format!("line {}: {}", self.line, open));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&value) = self.labels.get(&fixup.name) else {
+                return Err(format!(
+                    "line {}: undefined name {}",
+                    fixup.line, fixup.name
+                ));
+            };
+            let label = format!("label {}", fixup.name);
+            self.patch(fixup.addr, fixup.patch, &label, value)
+                .map_err(|e| format!("line {}: {}", fixup.line, e))?;
+        }
+
+        Ok(Program {
+            rom: self.memory[ORIGIN as usize..self.end as usize].to_vec(),
+            labels: self.labels,
+            breakpoints: self.breakpoints,
+        })
+    }
+
+    fn statement(&mut self, text: String) -> Result<(), String> {
+        if let Some(x) = self.register(&text) {
+            return self.assign(x);
+        }
+        if let Some(value) = parse_number(&text) {
+            return self.byte(value);
+        }
+        if self.macros.contains_key(&text) {
+            return self.expand(&text);
+        }
+
+        match text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let reg = self.next()?;
+                let reg = self
+                    .register(&reg)
+                    .ok_or_else(|| format!("expected a register, got {}", reg))?;
+                self.aliases.insert(name, reg);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.value(&value)?;
+                self.consts.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.consts.insert(name, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()?
+                } else {
+                    let value = self.next()?;
+                    self.value(&value)?
+                };
+                self.byte(value)?;
+            }
+            ":org" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()?
+                } else {
+                    let value = self.next()?;
+                    self.value(&value)?
+                };
+                self.here = address(value, XO_MEMORY_SIZE)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let addr = self.addr(Patch::Addr12)?;
+                self.emit(Instruction::Call { addr })?;
+            }
+            ":unpack" => {
+                let hi = self.next()?;
+                let hi = self.value(&hi)? as u8 & 0xF;
+                let addr = self.addr(Patch::Unpack)?;
+                self.emit(Instruction::LoadImm {
+                    x: 0,
+                    nn: hi << 4 | (addr >> 8) as u8 & 0xF,
+                })?;
+                self.emit(Instruction::LoadImm {
+                    x: 1,
+                    nn: addr as u8,
+                })?;
+            }
+            ":breakpoint" => {
+                let name = self.name()?;
+                self.breakpoints.push((name, self.here));
+            }
+
+            "clear" => self.emit(Instruction::Clear)?,
+            "return" | ";" => self.emit(Instruction::Return)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown { n })?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp { n })?;
+            }
+            "scroll-right" => self.emit(Instruction::ScrollRight)?,
+            "scroll-left" => self.emit(Instruction::ScrollLeft)?,
+            "exit" => self.emit(Instruction::Exit)?,
+            "lores" => self.emit(Instruction::Lores)?,
+            "hires" => self.emit(Instruction::Hires)?,
+            "jump" => {
+                let addr = self.addr(Patch::Addr12)?;
+                self.emit(Instruction::Jump { addr })?;
+            }
+            "jump0" => {
+                let addr = self.addr(Patch::Addr12)?;
+                self.emit(Instruction::JumpOffset {
+                    x: (addr >> 8) as usize,
+                    addr,
+                })?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n })?;
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::Bcd { x })?;
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let instruction = if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.expect_register()?;
+                    if text == "save" {
+                        Instruction::SaveRange { x, y }
+                    } else {
+                        Instruction::LoadRange { x, y }
+                    }
+                } else if text == "save" {
+                    Instruction::Store { x }
+                } else {
+                    Instruction::Load { x }
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::SaveFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::LoadFlags { x })?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                if n > 3 {
+                    return Err(format!("plane must be 0-3, got {}", n));
+                }
+                self.emit(Instruction::Plane { n })?;
+            }
+            "audio" => self.emit(Instruction::Audio)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                self.emit(match text.as_str() {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::Pitch { x },
+                })?;
+            }
+            "i" => self.assign_i()?,
+
+            "if" => {
+                let (x, cmp, rhs) = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.skip_unless(x, cmp, rhs)?,
+                    "begin" => {
+                        self.skip_unless(x, cmp.negate(), rhs)?;
+                        let jump = self.placeholder()?;
+                        self.blocks.push(Block::If { jump });
+                    }
+                    other => return Err(format!("expected then or begin, got {}", other)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let end = self.placeholder()?;
+                    self.patch(jump, Patch::Addr12, "else", self.here)?;
+                    self.blocks.push(Block::Else { jump: end });
+                }
+                _ => return Err("else without begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump } | Block::Else { jump }) => {
+                    self.patch(jump, Patch::Addr12, "end", self.here)?
+                }
+                _ => return Err("end without begin".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                let (x, cmp, rhs) = self.condition()?;
+                self.skip_unless(x, cmp.negate(), rhs)?;
+                let exit = self.placeholder()?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { exits, .. }) => exits.push(exit),
+                    _ => return Err("while outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    let addr = in_range("loop", start, Patch::Addr12)?;
+                    self.emit(Instruction::Jump { addr })?;
+                    for exit in exits {
+                        self.patch(exit, Patch::Addr12, "again", self.here)?;
+                    }
+                }
+                _ => return Err("again without loop".to_string()),
+            },
+
+            _ if text.starts_with(':') => return Err(format!("unknown directive {}", text)),
+
+            // Anything else is a call to a (possibly later) label
+            _ => {
+                check_name(&text)?;
+                let addr = self.resolve(&text, Patch::Addr12)?;
+                self.emit(Instruction::Call { addr })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `vX := ...`, `vX += ...` and the other register assignments.
+    fn assign(&mut self, x: usize) -> Result<(), String> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+
+        let instruction = match (op.as_str(), rhs.as_str()) {
+            (":=", "random") => {
+                let nn = self.imm()?;
+                Instruction::Random { x, nn }
+            }
+            (":=", "key") => Instruction::WaitKey { x },
+            (":=", "delay") => Instruction::GetDelay { x },
+            _ => match (op.as_str(), self.operand(&rhs)?) {
+                (":=", Operand::Reg(y)) => Instruction::Move { x, y },
+                (":=", Operand::Imm(nn)) => Instruction::LoadImm { x, nn },
+                ("+=", Operand::Reg(y)) => Instruction::Add { x, y },
+                ("+=", Operand::Imm(nn)) => Instruction::AddImm { x, nn },
+                ("-=", Operand::Reg(y)) => Instruction::Sub { x, y },
+                ("-=", Operand::Imm(nn)) => Instruction::AddImm {
+                    x,
+                    nn: nn.wrapping_neg(),
+                },
+                ("=-", Operand::Reg(y)) => Instruction::SubReverse { x, y },
+                ("|=", Operand::Reg(y)) => Instruction::Or { x, y },
+                ("&=", Operand::Reg(y)) => Instruction::And { x, y },
+                ("^=", Operand::Reg(y)) => Instruction::Xor { x, y },
+                (">>=", Operand::Reg(y)) => Instruction::ShiftRight { x, y },
+                ("<<=", Operand::Reg(y)) => Instruction::ShiftLeft { x, y },
+                _ => return Err(format!("invalid operation v{:x} {} {}", x, op, rhs)),
+            },
+        };
+
+        self.emit(instruction)
+    }
+
+    /// `i := ...` and `i += vX`.
+    fn assign_i(&mut self) -> Result<(), String> {
+        let op = self.next()?;
+
+        let instruction = match op.as_str() {
+            "+=" => Instruction::AddI {
+                x: self.expect_register()?,
+            },
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    Instruction::Font {
+                        x: self.expect_register()?,
+                    }
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    Instruction::BigFont {
+                        x: self.expect_register()?,
+                    }
+                }
+                Some("long") => {
+                    self.next()?;
+                    Instruction::LoadLongI {
+                        addr: self.addr(Patch::Long)?,
+                    }
+                }
+                _ => Instruction::LoadI {
+                    addr: self.addr(Patch::Addr12)?,
+                },
+            },
+            _ => return Err(format!("invalid operation i {}", op)),
+        };
+
+        self.emit(instruction)
+    }
+
+    /// Parses `vX op operand` after `if` or `while`.
+    fn condition(&mut self) -> Result<(usize, Cmp, Option<Operand>), String> {
+        let x = self.expect_register()?;
+        let op = self.next()?;
+
+        let cmp = match op.as_str() {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "key" => return Ok((x, Cmp::Key, None)),
+            "-key" => return Ok((x, Cmp::NotKey, None)),
+            "<" => Cmp::Lt,
+            ">" => Cmp::Gt,
+            "<=" => Cmp::Le,
+            ">=" => Cmp::Ge,
+            _ => return Err(format!("invalid comparison {}", op)),
+        };
+
+        let rhs = self.next()?;
+        Ok((x, cmp, Some(self.operand(&rhs)?)))
+    }
+
+    /// Emits the skip that makes the next instruction run only if the
+    /// condition holds. `<`, `>`, `<=` and `>=` compute the result in VF.
+    fn skip_unless(&mut self, x: usize, cmp: Cmp, rhs: Option<Operand>) -> Result<(), String> {
+        let instruction = match (cmp, rhs) {
+            (Cmp::Key, _) => Instruction::SkipNotKey { x },
+            (Cmp::NotKey, _) => Instruction::SkipKey { x },
+            (Cmp::Eq, Some(Operand::Imm(nn))) => Instruction::SkipNeImm { x, nn },
+            (Cmp::Eq, Some(Operand::Reg(y))) => Instruction::SkipNeReg { x, y },
+            (Cmp::Ne, Some(Operand::Imm(nn))) => Instruction::SkipEqImm { x, nn },
+            (Cmp::Ne, Some(Operand::Reg(y))) => Instruction::SkipEqReg { x, y },
+            (_, Some(rhs)) => {
+                // VF := rhs, then VF = rhs - vX (borrow flag set if rhs >= vX)
+                // or VF = vX - rhs (borrow flag set if vX >= rhs)
+                self.emit(match rhs {
+                    Operand::Reg(y) => Instruction::Move { x: 0xF, y },
+                    Operand::Imm(nn) => Instruction::LoadImm { x: 0xF, nn },
+                })?;
+                self.emit(match cmp {
+                    Cmp::Gt | Cmp::Le => Instruction::Sub { x: 0xF, y: x },
+                    _ => Instruction::SubReverse { x: 0xF, y: x },
+                })?;
+                let nn = match cmp {
+                    Cmp::Le | Cmp::Ge => 1,
+                    _ => 0,
+                };
+                Instruction::SkipNeImm { x: 0xF, nn }
+            }
+            (_, None) => unreachable!("comparison without operand"),
+        };
+
+        self.emit(instruction)
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut args = Vec::new();
+
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            args.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or_else(|| format!("unterminated macro {}", name))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with the macro's body.
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(format!("too many macro expansions (in {})", name));
+        }
+
+        let argc = self.macros[name].args.len();
+        let mut values = Vec::with_capacity(argc);
+        for _ in 0..argc {
+            values.push(self.next()?);
+        }
+
+        let line = self.line;
+        let body: Vec<Token> = {
+            let m = &self.macros[name];
+            m.body
+                .iter()
+                .map(|token| {
+                    let text = match m.args.iter().position(|arg| *arg == token.text) {
+                        Some(i) => values[i].clone(),
+                        None => token.text.clone(),
+                    };
+                    Token { text, line }
+                })
+                .collect()
+        };
+
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// Evaluates a `{ ... }` expression. Like Octo, operators have no
+    /// precedence and are evaluated right to left; use parentheses.
+    fn calc(&mut self) -> Result<f64, String> {
+        self.expect("{")?;
+
+        let mut expr = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "}" {
+                break;
+            }
+            expr.push(token);
+        }
+
+        let mut pos = 0;
+        let value = self.expr(&expr, &mut pos)?;
+        if pos != expr.len() {
+            return Err(format!("unexpected {} in expression", expr[pos]));
+        }
+        Ok(value)
+    }
+
+    fn expr(&self, expr: &[String], pos: &mut usize) -> Result<f64, String> {
+        let lhs = self.term(expr, pos)?;
+
+        let Some(op) = expr.get(*pos).filter(|op| *op != ")") else {
+            return Ok(lhs);
+        };
+        *pos += 1;
+        let rhs = self.expr(expr, pos)?;
+
+        let (a, b) = (lhs as i64, rhs as i64);
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" if rhs == 0.0 => return Err("division by zero".to_string()),
+            "/" => lhs / rhs,
+            "%" if b == 0 => return Err("division by zero".to_string()),
+            "%" => (a % b) as f64,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => (a << (b & 63)) as f64,
+            ">>" => (a >> (b & 63)) as f64,
+            "<" => (lhs < rhs) as i64 as f64,
+            ">" => (lhs > rhs) as i64 as f64,
+            "<=" => (lhs <= rhs) as i64 as f64,
+            ">=" => (lhs >= rhs) as i64 as f64,
+            "==" => (lhs == rhs) as i64 as f64,
+            "!=" => (lhs != rhs) as i64 as f64,
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "pow" => lhs.powf(rhs),
+            _ => return Err(format!("unknown operator {}", op)),
+        })
+    }
+
+    fn term(&self, expr: &[String], pos: &mut usize) -> Result<f64, String> {
+        let token = expr
+            .get(*pos)
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        *pos += 1;
+
+        let unary = |f: fn(f64) -> f64, pos: &mut usize| -> Result<f64, String> {
+            Ok(f(self.term(expr, pos)?))
+        };
+
+        match token.as_str() {
+            "(" => {
+                let value = self.expr(expr, pos)?;
+                if expr.get(*pos).map(String::as_str) != Some(")") {
+                    return Err("missing )".to_string());
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            "-" => unary(|v| -v, pos),
+            "~" => unary(|v| !(v as i64) as f64, pos),
+            "!" => unary(|v| (v == 0.0) as i64 as f64, pos),
+            "abs" => unary(f64::abs, pos),
+            "sqrt" => unary(f64::sqrt, pos),
+            "sin" => unary(f64::sin, pos),
+            "cos" => unary(f64::cos, pos),
+            "floor" => unary(f64::floor, pos),
+            "ceil" => unary(f64::ceil, pos),
+            "@" => {
+                let addr = self.term(expr, pos)?;
+                Ok(self.memory[address(addr, XO_MEMORY_SIZE)? as usize] as f64)
+            }
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "HERE" => Ok(self.here as f64),
+            _ => self.value(token),
+        }
+    }
+
+    /// A number, constant or already defined label.
+    fn value(&self, token: &str) -> Result<f64, String> {
+        if let Some(value) = parse_number(token) {
+            return Ok(value);
+        }
+        if let Some(&value) = self.consts.get(token) {
+            return Ok(value);
+        }
+        if let Some(&addr) = self.labels.get(token) {
+            return Ok(addr as f64);
+        }
+        Err(format!("undefined name {}", token))
+    }
+
+    /// An address operand; labels that aren't defined yet are patched later.
+    fn addr(&mut self, patch: Patch) -> Result<u16, String> {
+        let token = self.next()?;
+
+        if let Some(value) = parse_number(&token).or_else(|| self.consts.get(&token).copied()) {
+            return address(value, patch.limit());
+        }
+
+        check_name(&token)?;
+        self.resolve(&token, patch)
+    }
+
+    /// The label's address, or 0 plus a fixup if it isn't defined yet.
+    fn resolve(&mut self, name: &str, patch: Patch) -> Result<u16, String> {
+        match self.labels.get(name) {
+            Some(&addr) => in_range(&format!("label {}", name), addr, patch),
+            None => {
+                self.fixup(patch, name.to_string());
+                Ok(0)
+            }
+        }
+    }
+
+    fn fixup(&mut self, patch: Patch, name: String) {
+        self.fixups.push(Fixup {
+            addr: self.here,
+            patch,
+            name,
+            line: self.line,
+        });
+    }
+
+    /// Fills in the address of `target` (for error messages) at `addr`.
+    fn patch(&mut self, addr: u16, patch: Patch, target: &str, value: u16) -> Result<(), String> {
+        let value = in_range(target, value, patch)?;
+        let addr = addr as usize;
+        match patch {
+            Patch::Addr12 => {
+                self.memory[addr] = self.memory[addr] & 0xF0 | (value >> 8) as u8 & 0xF;
+                self.memory[addr + 1] = value as u8;
+            }
+            Patch::Long => {
+                self.memory[addr + 2] = (value >> 8) as u8;
+                self.memory[addr + 3] = value as u8;
+            }
+            Patch::Unpack => {
+                self.memory[addr + 1] = self.memory[addr + 1] & 0xF0 | (value >> 8) as u8 & 0xF;
+                self.memory[addr + 3] = value as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits a jump whose target is filled in when its block closes.
+    fn placeholder(&mut self) -> Result<u16, String> {
+        let addr = self.here;
+        self.emit(Instruction::Jump { addr: 0 })?;
+        Ok(addr)
+    }
+
+    fn label(&mut self, name: String, addr: u16) -> Result<(), String> {
+        check_name(&name)?;
+        if self.labels.contains_key(&name) {
+            return Err(format!("label {} is already defined", name));
+        }
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), String> {
+        for byte in instruction.encode() {
+            self.write(byte)?;
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, value: f64) -> Result<(), String> {
+        let byte = to_byte(value)?;
+        self.write(byte)
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), String> {
+        if self.here < ORIGIN {
+            return Err(format!("cannot write below 0x{:03X}", ORIGIN));
+        }
+
+        self.memory[self.here as usize] = byte;
+        self.here = self
+            .here
+            .checked_add(1)
+            .ok_or_else(|| "program does not fit in memory".to_string())?;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn register(&self, token: &str) -> Option<usize> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+
+        let digit = token.strip_prefix(['v', 'V'])?;
+        match digit.len() {
+            1 => usize::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<usize, String> {
+        let token = self.next()?;
+        self.register(&token)
+            .ok_or_else(|| format!("expected a register, got {}", token))
+    }
+
+    fn operand(&self, token: &str) -> Result<Operand, String> {
+        match self.register(token) {
+            Some(reg) => Ok(Operand::Reg(reg)),
+            None => Ok(Operand::Imm(to_byte(self.value(token)?)?)),
+        }
+    }
+
+    fn imm(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        to_byte(self.value(&token)?)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+        if !(0.0..16.0).contains(&value) {
+            return Err(format!("{} does not fit in 4 bits", token));
+        }
+        Ok(value as u8)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let name = self.next()?;
+        check_name(&name)?;
+        Ok(name)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(format!("expected {}, got {}", expected, token));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+}
+
+/// Splits source into whitespace separated tokens; `#` starts a comment.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (i, line) in source.lines().enumerate() {
+        for word in line.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            tokens.push_back(Token {
+                text: word.to_string(),
+                line: i + 1,
+            });
+        }
+    }
+
+    tokens
+}
+
+/// Decimal, `0x` hex or `0b` binary, optionally negative.
+fn parse_number(token: &str) -> Option<f64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+fn to_byte(value: f64) -> Result<u8, String> {
+    if !(-128.0..256.0).contains(&value) {
+        return Err(format!("{} does not fit in a byte", value));
+    }
+    Ok(value as i64 as u8)
+}
+
+fn address(value: f64, limit: usize) -> Result<u16, String> {
+    if !(0.0..limit as f64).contains(&value) {
+        return Err(format!("address {} is out of range", value));
+    }
+    Ok(value as u16)
+}
+
+/// Rejects jump targets and other addresses that don't fit the operand.
+fn in_range(target: &str, addr: u16, patch: Patch) -> Result<u16, String> {
+    if addr as usize >= patch.limit() {
+        return Err(format!("{} at 0x{:04X} is out of range", target, addr));
+    }
+    Ok(addr)
+}
+
+/// Rejects tokens that can't be label or constant names.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid name {}", name))
+    }
+}
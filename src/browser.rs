@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sdl2::rect::Rect;
+
+use crate::display::Display;
+
+/// File extensions shown in the browser.
+pub const ROM_EXTENSIONS: &[&str] = &["ch8", "sc8", "xo8"];
+
+enum Entry {
+    Parent,
+    Dir(PathBuf),
+    Rom(PathBuf),
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        let name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        match self {
+            Entry::Parent => "../".to_string(),
+            Entry::Dir(path) => format!("{}/", name(path)),
+            Entry::Rom(path) => name(path),
+        }
+    }
+}
+
+/**
+ * In-window ROM file browser (F4).
+ *
+ * Lists the subdirectories and ROM files of a directory. Directories can be
+ * entered; selecting a ROM returns its path to the caller.
+ */
+pub struct RomBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl RomBrowser {
+    pub fn new(dir: &Path) -> RomBrowser {
+        let mut browser = RomBrowser {
+            dir: dir.to_path_buf(),
+            entries: Vec::new(),
+            selected: 0,
+        };
+
+        browser.refresh();
+        browser
+    }
+
+    fn refresh(&mut self) {
+        let mut dirs = Vec::new();
+        let mut roms = Vec::new();
+
+        if let Ok(read_dir) = fs::read_dir(&self.dir) {
+            for path in read_dir.flatten().map(|entry| entry.path()) {
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if is_rom(&path) {
+                    roms.push(path);
+                }
+            }
+        }
+
+        dirs.sort();
+        roms.sort();
+
+        self.entries = Vec::new();
+        if self.dir.parent().is_some() {
+            self.entries.push(Entry::Parent);
+        }
+        self.entries.extend(dirs.into_iter().map(Entry::Dir));
+        self.entries.extend(roms.into_iter().map(Entry::Rom));
+        self.selected = 0;
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Goes to the parent directory.
+    pub fn back(&mut self) {
+        if let Some(parent) = self.dir.parent() {
+            self.dir = parent.to_path_buf();
+            self.refresh();
+        }
+    }
+
+    /// Enters the selected directory, or returns the selected ROM.
+    pub fn select(&mut self) -> Option<PathBuf> {
+        match self.entries.get(self.selected)? {
+            Entry::Parent => self.back(),
+            Entry::Dir(path) => {
+                self.dir = path.clone();
+                self.refresh();
+            }
+            Entry::Rom(path) => return Some(path.clone()),
+        }
+
+        None
+    }
+
+    pub fn render(&self, display: &mut Display) -> Result<(), String> {
+        let bg = display.color(0);
+        let fg = display.color(1);
+        let (window_width, window_height) = display.canvas.window().size();
+        let line_height = display
+            .font
+            .as_ref()
+            .ok_or("No font loaded")?
+            .recommended_line_spacing()
+            .max(1);
+
+        display.canvas.set_draw_color(bg);
+        display.canvas.clear();
+
+        let title = format!("Load ROM: {}", self.dir.display());
+        display.draw_text(&title, 8, 4, fg)?;
+
+        // Keep the selection in view
+        let visible = ((window_height as i32 - line_height * 2) / line_height).max(1) as usize;
+        let first = self.selected.saturating_sub(visible - 1);
+
+        if self.entries.is_empty() {
+            display.draw_text("(no ROMs here)", 8, 4 + line_height * 2, fg)?;
+        }
+
+        for (row, (i, entry)) in self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .enumerate()
+        {
+            let y = 4 + line_height * (row as i32 + 2);
+            let color = if i == self.selected {
+                display.canvas.set_draw_color(fg);
+                display
+                    .canvas
+                    .fill_rect(Rect::new(0, y, window_width, line_height as u32))?;
+                bg
+            } else {
+                fg
+            };
+
+            display.draw_text(&entry.label(), 16, y, color)?;
+        }
+
+        display.canvas.present();
+        Ok(())
+    }
+}
+
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
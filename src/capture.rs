@@ -0,0 +1,354 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    consts::{HIRES_HEIGHT, HIRES_WIDTH},
+    framebuffer::Framebuffer,
+    palette::{Palette, MAX_COLORS},
+    video::VideoSink,
+};
+
+/// Video file formats the framebuffer can be recorded as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VideoFormat {
+    #[default]
+    Gif, // Animated GIF with the exact palette colors
+    Y4m, // Uncompressed YUV 4:4:4 at 60 fps, for piping into encoders
+}
+
+impl FromStr for VideoFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<VideoFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "gif" => Ok(VideoFormat::Gif),
+            "y4m" => Ok(VideoFormat::Y4m),
+            _ => Err(format!("Unknown video format: {} (expected gif or y4m)", s)),
+        }
+    }
+}
+
+impl VideoFormat {
+    /// Guesses the format from a file extension.
+    pub fn from_path(path: &Path) -> Option<VideoFormat> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Y4m => "y4m",
+        }
+    }
+}
+
+/**
+ * Records every emulated frame to a GIF or Y4M file.
+ *
+ * Hand it each frame as it is emulated through `VideoSink::present`, not
+ * only the ones that reach the screen: the video's timing comes from the
+ * frame count alone, so it plays back at the right speed whether it was
+ * recorded in real time, in turbo or headless.
+ *
+ * Videos are always 128x64; low resolution frames are doubled, so programs
+ * can switch modes mid-recording. The palette is fixed when recording
+ * starts.
+ *
+ * GIF delays are in hundredths of a second and most viewers won't go below
+ * 2, so frames that stay unchanged are merged into one and frames shown for
+ * a single 1/60 s may be dropped. The total length is kept exact.
+ */
+pub struct VideoRecorder {
+    format: VideoFormat,
+    path: PathBuf,
+    out: BufWriter<File>,
+    frames: u32,                   // Emulated frames recorded
+    error: Option<String>,         // First write error, reported by `finish`
+    colors: [[u8; 3]; MAX_COLORS], // Palette when recording started
+    frame: Vec<u8>,                // Pixel values of the latest frame, at 128x64
+    pending: Option<Vec<u8>>,      // GIF: picture shown since `written`, not written yet
+    written: u32,                  // GIF: hundredths of a second written so far
+}
+
+impl VideoRecorder {
+    /// Starts recording to `path`, in `format` or else the format its
+    /// extension names.
+    pub fn create(
+        path: &Path,
+        format: Option<VideoFormat>,
+        palette: &Palette,
+    ) -> Result<VideoRecorder, String> {
+        let format = format
+            .or_else(|| VideoFormat::from_path(path))
+            .ok_or_else(|| format!("Unknown video format for {}", path.display()))?;
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+        let mut recorder = VideoRecorder {
+            format,
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+            frames: 0,
+            error: None,
+            colors: palette.table(),
+            frame: vec![0; HIRES_WIDTH * HIRES_HEIGHT],
+            pending: None,
+            written: 0,
+        };
+
+        let header = match format {
+            VideoFormat::Gif => recorder.gif_header(),
+            VideoFormat::Y4m => {
+                let header = format!(
+                    "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444\n",
+                    HIRES_WIDTH, HIRES_HEIGHT
+                );
+                recorder.out.write_all(header.as_bytes())
+            }
+        };
+        header.map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of emulated frames recorded so far.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Writes the end of the file. Returns how many frames were recorded.
+    pub fn finish(mut self) -> Result<u32, String> {
+        if self.format == VideoFormat::Gif {
+            let end = centiseconds(self.frames);
+            if let Some(pending) = self.pending.take() {
+                let delay = end.saturating_sub(self.written).max(2);
+                let result = self.gif_frame(&pending, delay);
+                self.check(result);
+            }
+            let result = self.out.write_all(&[0x3B]);
+            self.check(result);
+        }
+
+        let result = self.out.flush();
+        self.check(result);
+
+        match self.error {
+            Some(e) => Err(format!("Failed to write {}: {}", self.path.display(), e)),
+            None => Ok(self.frames),
+        }
+    }
+
+    /// Notes the first error; later frames are dropped.
+    fn check(&mut self, result: std::io::Result<()>) {
+        if let Err(e) = result {
+            self.error.get_or_insert(e.to_string());
+        }
+    }
+
+    fn record(&mut self, pixels: &Framebuffer) -> std::io::Result<()> {
+        // Low resolution frames are doubled to fill the 128x64 video
+        let scale = HIRES_WIDTH / pixels.width().max(1);
+        for (y, row) in self.frame.chunks_exact_mut(HIRES_WIDTH).enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = pixels.get(x / scale, y / scale);
+            }
+        }
+
+        match self.format {
+            VideoFormat::Gif => self.gif_record(),
+            VideoFormat::Y4m => self.y4m_frame(),
+        }
+    }
+
+    fn y4m_frame(&mut self) -> std::io::Result<()> {
+        let yuv = self.colors.map(ycbcr);
+
+        // Planar: all Y samples, then all Cb, then all Cr
+        let mut planes: [Vec<u8>; 3] = Default::default();
+        for &pixel in &self.frame {
+            for (plane, sample) in planes.iter_mut().zip(yuv[pixel as usize % MAX_COLORS]) {
+                plane.push(sample);
+            }
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        for plane in planes {
+            self.out.write_all(&plane)?;
+        }
+        Ok(())
+    }
+
+    fn gif_header(&mut self) -> std::io::Result<()> {
+        self.out.write_all(b"GIF89a")?;
+        self.out.write_all(&(HIRES_WIDTH as u16).to_le_bytes())?;
+        self.out.write_all(&(HIRES_HEIGHT as u16).to_le_bytes())?;
+        // Global color table of 16 entries, 8 bits per channel
+        self.out.write_all(&[0xF3, 0, 0])?;
+        for color in self.colors {
+            self.out.write_all(&color)?;
+        }
+
+        // Loop forever
+        self.out
+            .write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")
+    }
+
+    /// Writes the pending frame once the picture changes and it has been
+    /// shown long enough to get a delay of its own.
+    fn gif_record(&mut self) -> std::io::Result<()> {
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                self.pending = Some(self.frame.clone());
+                return Ok(());
+            }
+        };
+
+        if pending != self.frame {
+            // The new picture first shows in frame `self.frames`
+            let now = centiseconds(self.frames);
+            if now - self.written >= 2 {
+                self.gif_frame(&pending, now - self.written)?;
+                self.written = now;
+            }
+            pending.copy_from_slice(&self.frame);
+        }
+
+        self.pending = Some(pending);
+        Ok(())
+    }
+
+    fn gif_frame(&mut self, pixels: &[u8], mut delay: u32) -> std::io::Result<()> {
+        // Delays longer than the field allows are split over copies
+        while delay > 0 {
+            let chunk = delay.min(u16::MAX as u32);
+            delay -= chunk;
+
+            // Graphic control extension: keep the previous frame, no transparency
+            self.out.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+            self.out.write_all(&(chunk as u16).to_le_bytes())?;
+            self.out.write_all(&[0x00, 0x00])?;
+
+            // Image descriptor covering the whole screen
+            self.out.write_all(&[0x2C, 0, 0, 0, 0])?;
+            self.out.write_all(&(HIRES_WIDTH as u16).to_le_bytes())?;
+            self.out.write_all(&(HIRES_HEIGHT as u16).to_le_bytes())?;
+            self.out.write_all(&[0x00])?;
+
+            self.out.write_all(&[GIF_MIN_CODE_SIZE])?;
+            for block in lzw(pixels).chunks(255) {
+                self.out.write_all(&[block.len() as u8])?;
+                self.out.write_all(block)?;
+            }
+            self.out.write_all(&[0x00])?;
+        }
+        Ok(())
+    }
+}
+
+impl VideoSink for VideoRecorder {
+    fn present(&mut self, pixels: &Framebuffer) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = self.record(pixels);
+        self.check(result);
+        self.frames += 1;
+    }
+}
+
+/// Start of frame `frame` in hundredths of a second, rounded.
+fn centiseconds(frame: u32) -> u32 {
+    ((frame as u64 * 100 + 30) / 60) as u32
+}
+
+/// BT.601 limited range Y, Cb and Cr for an RGB color.
+fn ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y, cb, cr].map(|v| v.round() as u8)
+}
+
+/// Bits per pixel value in the GIF's LZW stream (16 colors).
+const GIF_MIN_CODE_SIZE: u8 = 4;
+
+/**
+ * GIF flavored LZW compression of pixel values below 16.
+ *
+ * Codes start one bit wider than the minimum code size and grow as the
+ * table fills, up to 12 bits, after which the table is cleared. Codes are
+ * packed least significant bit first.
+ */
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << GIF_MIN_CODE_SIZE;
+    let end = clear + 1;
+
+    let mut out = Vec::new();
+    let (mut bits, mut bit_count) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        bits |= (code as u32) << bit_count;
+        bit_count += size;
+        while bit_count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = GIF_MIN_CODE_SIZE as u32 + 1;
+    emit(clear, size, &mut out);
+
+    let mut pixels = pixels.iter().map(|&pixel| pixel & (clear as u8 - 1));
+    let mut prefix = match pixels.next() {
+        Some(pixel) => pixel as u16,
+        None => {
+            emit(end, size, &mut out);
+            return out;
+        }
+    };
+
+    for pixel in pixels {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+
+        emit(prefix, size, &mut out);
+        if next == 4096 {
+            emit(clear, size, &mut out);
+            table.clear();
+            next = end + 1;
+            size = GIF_MIN_CODE_SIZE as u32 + 1;
+        } else {
+            // Decoders add each code a step later, so widen once the code
+            // being added no longer fits
+            if next >= 1 << size {
+                size += 1;
+            }
+            table.insert((prefix, pixel), next);
+            next += 1;
+        }
+        prefix = pixel as u16;
+    }
+
+    emit(prefix, size, &mut out);
+    emit(end, size, &mut out);
+    if bit_count > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
@@ -0,0 +1,158 @@
+use crate::{
+    cpu::Cpu,
+    debugger::Debugger,
+    error::{Chip8Error, FaultPolicy},
+    framebuffer::Framebuffer,
+    quirks::Quirks,
+    video::VideoSink,
+};
+
+#[cfg(feature = "savestate")]
+use crate::state;
+#[cfg(feature = "savestate")]
+use std::path::Path;
+
+/**
+ * A complete Chip-8 machine: CPU state, memory, framebuffer and keypad.
+ *
+ * This is the frontend-independent entry point to the emulator. A frontend
+ * feeds key state in through `keypad_mut`, runs `frame` once per 60 Hz tick
+ * and then hands the result to a `VideoSink` with `present`.
+ *
+ * When the CPU faults, `fault_policy` decides whether it halts, skips the
+ * instruction or traps into the debugger; the fault is also returned to the
+ * frontend so it can be reported.
+ */
+pub struct Machine {
+    pub cpu: Cpu,
+    pub debugger: Debugger,
+    pub fault_policy: FaultPolicy,
+    completed: bool, // The last `frame` ran in full and ticked the timers
+}
+
+impl Machine {
+    pub fn new(hz: u32, debug: bool, quirks: Quirks) -> Machine {
+        Machine {
+            cpu: Cpu::new(hz, quirks),
+            debugger: Debugger::with_trace(debug),
+            fault_policy: FaultPolicy::default(),
+            completed: false,
+        }
+    }
+
+    pub fn load_rom(&mut self, file_name: &str) -> Result<(), Chip8Error> {
+        self.cpu.load_rom(file_name)
+    }
+
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.cpu.load_rom_bytes(rom)
+    }
+
+    /// Replaces the running program: resets the CPU and its memory, keeping
+    /// the current configuration, and loads `rom`.
+    /// Fails without touching the current program if `rom` can't be loaded.
+    pub fn replace_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let mut cpu = Cpu::new(self.cpu.hz, self.cpu.quirks);
+        cpu.load_rom_bytes(rom)?;
+        cpu.seed_rng(self.cpu.rng().seed(), self.cpu.rng().mode());
+        cpu.redraw = true;
+        self.cpu = cpu;
+        Ok(())
+    }
+
+    #[cfg(feature = "savestate")]
+    pub fn save_state(&self, path: &Path) -> Result<(), String> {
+        state::save(&self.cpu, path)
+    }
+
+    #[cfg(feature = "savestate")]
+    pub fn load_state(&mut self, path: &Path) -> Result<(), String> {
+        state::load(&mut self.cpu, path)
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        self.cpu.tick().map_err(|e| self.fault(e))
+    }
+
+    /// Runs one 60 Hz frame: a frame's worth of instructions at `cpu.hz`,
+    /// then one tick of the delay and sound timers. Stops early if the
+    /// debugger pauses execution or the CPU faults.
+    ///
+    /// Timers only advance with emulated frames, so they keep pace with the
+    /// program whether frames run in real time, faster, slower or headless.
+    pub fn frame(&mut self) -> Result<(), Chip8Error> {
+        self.cpu.vblank();
+        let mut result = Ok(());
+
+        for _ in 0..self.cpu.frame_budget() {
+            if !self.debugger.should_run(&self.cpu) {
+                break;
+            }
+
+            if let Err(e) = self.cpu.tick() {
+                result = Err(self.fault(e));
+                break;
+            }
+            self.debugger.after_tick(&self.cpu);
+        }
+
+        // A frame cut short by the debugger doesn't count
+        self.completed = !self.debugger.is_paused();
+        if self.completed {
+            self.cpu.tick_timers();
+        }
+
+        self.debugger.end_frame();
+        result
+    }
+
+    /// True if the last call to `frame` emulated a whole 60 Hz frame, false
+    /// if the debugger held it back. Anything that follows emulated time,
+    /// like videos or rewind history, should only count completed frames.
+    pub fn frame_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Applies the fault policy and passes the fault on.
+    fn fault(&mut self, e: Chip8Error) -> Chip8Error {
+        match self.fault_policy {
+            FaultPolicy::Halt => self.cpu.halted = true,
+            FaultPolicy::Skip => self.cpu.skip_instruction(),
+            FaultPolicy::Trap => self.debugger.pause(),
+        }
+        e
+    }
+
+    /// Hands the framebuffer to `sink` if it changed since the last call.
+    /// Returns true if a frame was presented.
+    pub fn present<S: VideoSink + ?Sized>(&mut self, sink: &mut S) -> bool {
+        if !self.take_redraw() {
+            return false;
+        }
+
+        sink.present(&self.cpu.pixels);
+        true
+    }
+
+    /// Returns and clears the framebuffer's dirty flag.
+    pub fn take_redraw(&mut self) -> bool {
+        std::mem::take(&mut self.cpu.redraw)
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    pub fn pixels(&self) -> &Framebuffer {
+        &self.cpu.pixels
+    }
+
+    pub fn keypad_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.cpu.keypad
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.cpu.sound_timer > 0
+    }
+}
@@ -5,7 +5,18 @@ use crate::keymaps::Keymap;
 
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 pub const SCALE_FACTOR: u32 = 10;
+pub const MEMORY_SIZE: usize = 0x1000; // 4 KiB (CHIP-8, SUPER-CHIP)
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - 0x200;
+pub const XO_MEMORY_SIZE: usize = 0x10000; // 64 KiB (XO-CHIP)
+pub const XO_MAX_ROM_SIZE: usize = XO_MEMORY_SIZE - 0x200;
+pub const DEFAULT_PITCH: u8 = 64; // XO-CHIP pitch for 4000 Hz pattern playback
+pub const BIG_FONT_OFFSET: usize = 80; // Start of the Super CHIP fonts in FONT
+pub const DEFAULT_HZ: u32 = 480; // Instructions per second
+pub const MIN_HZ: u32 = 60;
+pub const MAX_HZ: u32 = 10_000_000;
 pub const FONT: [u8; 240] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -34,7 +45,7 @@ pub const FONT: [u8; 240] = [
     0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
     0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
     0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
-    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFf, 0xC3, 0xC3, 0xC3, // A
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
     0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
     0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
     0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
@@ -1,19 +1,40 @@
-use crate::{consts::*, display::Display};
-use rand::Rng;
-use std::{fs::File, io::Read, path::Path};
+use crate::{
+    consts::*,
+    error::Chip8Error,
+    framebuffer::Framebuffer,
+    instruction::Instruction,
+    quirks::Quirks,
+    rng::{Rng, RngMode},
+    state::{self, CpuState},
+};
+#[cfg(feature = "savestate")]
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Progress of an FX0A wait-for-key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "savestate", derive(Serialize, Deserialize))]
+pub enum KeyWait {
+    #[default]
+    Idle, // Not executing FX0A
+    Press,       // Waiting for any key to go down
+    Release(u8), // Waiting for this key to come back up
+}
 
 pub struct Cpu {
     // Config
-    pub speed: u8, // CPU speed
-    pub debug: bool,
+    pub hz: u32, // Instructions per second
+    pub quirks: Quirks,
 
     // Interaction
-    pub pixels: [[bool; WIDTH]; HEIGHT],
+    pub pixels: Framebuffer,
+    pub redraw: bool, // Set when `pixels` changed since the last frame was drawn
     pub keypad: [u8; 16],
 
     // CPU
+    rom_hash: u64,
     opcode: u16,
-    memory: Box<[u8; 4096]>,
+    memory: Box<[u8]>,
     vreg: [u8; 16],   // 8-bit general purpose registers
     ireg: u16,        // Index register
     pc: u16,          // Program Counter
@@ -21,27 +42,33 @@ pub struct Cpu {
     sp: u8,           // Stack Pointer
     delay_timer: u8,
     pub sound_timer: u8,
-    sttick: f32, // Sound timer tick
-    tick: f32,   // CPU timer tick
+    rpl: [u8; 16],                       // RPL user flags (FX75/FX85)
+    pub halted: bool,                    // Set by 00FD
+    planes: u8,                          // Bitplanes selected by FN01 (XO-CHIP)
+    pub audio_pattern: Option<[u8; 16]>, // 1-bit audio pattern loaded by F002 (XO-CHIP)
+    pub pitch: u8,                       // Audio pattern playback pitch set by FX3A (XO-CHIP)
+    rng: Rng,                            // Source of CXNN random numbers
+    key_wait: KeyWait,                   // FX0A progress
+    cycles: u32,                         // Instructions owed from earlier frames, in 1/60ths
+    drawn: bool,                         // A sprite was drawn this frame (display wait quirk)
 }
 
 impl Cpu {
-    pub fn new(speed: u8, debug: bool) -> Cpu {
-        let mut memory = [0; 4096];
-
-        for i in 0..240 {
-            memory[i] = FONT[i];
-        }
+    pub fn new(hz: u32, quirks: Quirks) -> Cpu {
+        let mut memory = vec![0; quirks.memory_size()];
+        memory[..FONT.len()].copy_from_slice(&FONT);
 
         Cpu {
-            speed: speed,
-            debug: debug,
+            hz,
+            quirks,
 
-            pixels: [[false; WIDTH]; HEIGHT],
+            pixels: Framebuffer::lores(),
+            redraw: false,
             keypad: [0; 16],
 
+            rom_hash: state::fnv1a(&[]),
             opcode: 0,
-            memory: Box::new(memory),
+            memory: memory.into_boxed_slice(),
             vreg: [0; 16],
             ireg: 0,
             pc: 0x200,
@@ -49,342 +76,699 @@ impl Cpu {
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
-            sttick: 0.0,
-            tick: 0.0,
+            rpl: [0; 16],
+            halted: false,
+            planes: 1,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng: Rng::default(),
+            key_wait: KeyWait::Idle,
+            cycles: 0,
+            drawn: false,
         }
     }
 
-    pub fn load_rom(&mut self, file_name: &str) {
-        let path = Path::new(file_name);
-        let mut file =
-            File::open(&path).expect(format!("Failed to open ROM: {}", file_name).as_str());
-        let mut buf: Vec<u8> = Vec::new();
+    pub fn load_rom(&mut self, file_name: &str) -> Result<(), Chip8Error> {
+        let buf = fs::read(file_name).map_err(|e| Chip8Error::Io {
+            path: file_name.to_string(),
+            message: e.to_string(),
+        })?;
 
-        file.read_to_end(&mut buf)
-            .expect(format!("Failed to read ROM: {}", file_name).as_str());
+        self.load_rom_bytes(&buf)
+    }
 
-        if buf.len() >= 3585 {
-            panic!("ROM is too large, size: {} > 3584", buf.len());
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - 0x200;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
         }
 
-        let buf_len = buf.len();
+        self.memory[512..512 + rom.len()].copy_from_slice(rom);
+        self.rom_hash = state::fnv1a(rom);
+        Ok(())
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn ireg(&self) -> u16 {
+        self.ireg
+    }
+
+    pub fn vreg(&self) -> &[u8; 16] {
+        &self.vreg
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..(self.sp as usize).min(self.stack.len())]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// True while FX0A is blocking the CPU until a key is pressed and released.
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    /// Reads a byte of memory. Addresses past the end of memory read as 0.
+    pub fn read(&self, addr: u16) -> u8 {
+        self.memory.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    /// Bytes of memory, 4 KiB or 64 KiB depending on the platform.
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Reads the big-endian word at `addr`.
+    pub fn read_word(&self, addr: u16) -> u16 {
+        (self.read(addr) as u16) << 8 | self.read(addr.wrapping_add(1)) as u16
+    }
+
+    /// The opcode at PC, i.e. the next instruction to be executed.
+    pub fn peek_opcode(&self) -> u16 {
+        self.read_word(self.pc)
+    }
+
+    /// The next instruction to be executed, decoded.
+    pub fn peek_instruction(&self) -> Instruction {
+        Instruction::decode(self.peek_opcode(), self.read_word(self.pc.wrapping_add(2)))
+    }
+
+    /// Hash of the loaded ROM, identifying which game a save state belongs to.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
 
-        for i in 0..buf_len {
-            self.memory[i + 512] = buf[i];
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Switches quirks. Memory grows or shrinks to the platform's size,
+    /// keeping what fits.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        if self.memory.len() != quirks.memory_size() {
+            let mut memory = self.memory.to_vec();
+            memory.resize(quirks.memory_size(), 0);
+            self.memory = memory.into_boxed_slice();
         }
+    }
 
-        println!("Loaded ROM: {} ({} bytes) ", file_name, buf_len);
+    /// Restarts the CXNN random sequence from `seed`.
+    pub fn seed_rng(&mut self, seed: u64, mode: RngMode) {
+        self.rng = Rng::new(seed, mode);
     }
 
-    pub fn tick(&mut self, display: &mut Display) {
-        self.opcode = (self.memory[self.pc as usize] as u16) << 8
-            | (self.memory[self.pc as usize + 1] as u16);
+    pub fn snapshot(&self) -> CpuState {
+        CpuState {
+            memory: self.memory.to_vec(),
+            vreg: self.vreg,
+            ireg: self.ireg,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            cycles: self.cycles,
+            rpl: self.rpl,
+            halted: self.halted,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng.clone(),
+            key_wait: self.key_wait,
+            drawn: self.drawn,
+            pixels: self.pixels.clone(),
+            keypad: self.keypad,
+        }
+    }
 
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
-        let nn: u8 = (self.opcode & 0x00FF) as u8; // u8, byte 8-bit value
+    pub fn restore(&mut self, state: &CpuState) {
+        self.memory.copy_from_slice(&state.memory);
+        self.vreg = state.vreg;
+        self.ireg = state.ireg;
+        self.pc = state.pc;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.cycles = state.cycles;
+        self.rpl = state.rpl;
+        self.halted = state.halted;
+        self.planes = state.planes;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.rng = state.rng.clone();
+        self.key_wait = state.key_wait;
+        self.drawn = state.drawn;
+        self.pixels = state.pixels.clone();
+        self.keypad = state.keypad;
+        self.redraw = true;
+    }
 
-        let nnn: u16 = (self.opcode & 0x0FFF) as u16; // addr 12-bit value
+    /// Executes one instruction. On a fault the CPU is left unchanged, with
+    /// PC pointing at the faulting instruction.
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        if self.halted {
+            return Ok(());
+        }
 
-        if self.debug {
-            println!(
-                "Opcode: 0x{:X} Vx: 0x{:X} Vy: 0x{:X} N: 0x{:X} NNN: 0x{:X}",
-                self.opcode, vx, vy, nn, nnn
-            );
+        // Leave room for the longest advance (skipping over F000 NNNN)
+        if self.pc as usize + 6 >= self.memory.len() {
+            return Err(Chip8Error::MemoryFault {
+                pc: self.pc,
+                addr: self.pc as usize,
+            });
         }
-        match self.opcode & 0xF000 {
-            0x0000 => match self.opcode & 0x00FF {
-                // 00E0 Clear screen
-                0x00E0 => {
-                    self.pixels = [[false; WIDTH]; HEIGHT];
-                    self.pc += 2;
-                }
 
-                // 00EE Return from subroutine
-                0x00EE => {
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp as usize];
-                    self.pc += 2;
+        self.opcode = self.read_word(self.pc);
+        let instruction = Instruction::decode(self.opcode, self.read_word(self.pc.wrapping_add(2)));
+
+        match instruction {
+            // 00E0 Clear screen
+            Instruction::Clear => {
+                self.pixels.clear(self.planes);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00CN Scroll display down N lines (Super CHIP)
+            Instruction::ScrollDown { n } => {
+                self.pixels.scroll_down(n as usize, self.planes);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00DN Scroll display up N lines (XO-CHIP)
+            Instruction::ScrollUp { n } => {
+                self.pixels.scroll_up(n as usize, self.planes);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00FB Scroll display right 4 pixels (Super CHIP)
+            Instruction::ScrollRight => {
+                self.pixels.scroll_right(4, self.planes);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00FC Scroll display left 4 pixels (Super CHIP)
+            Instruction::ScrollLeft => {
+                self.pixels.scroll_left(4, self.planes);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00FD Exit interpreter (Super CHIP)
+            Instruction::Exit => {
+                self.halted = true;
+            }
+
+            // 00FE Switch to 64x32 low resolution mode (Super CHIP)
+            Instruction::Lores => {
+                self.pixels.resize(WIDTH, HEIGHT);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00FF Switch to 128x64 high resolution mode (Super CHIP)
+            Instruction::Hires => {
+                self.pixels.resize(HIRES_WIDTH, HIRES_HEIGHT);
+                self.redraw = true;
+                self.pc += 2;
+            }
+
+            // 00EE Return from subroutine
+            Instruction::Return => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { pc: self.pc });
                 }
-                _ => println!("Unknown opcode: 00{:X}", self.opcode),
-            },
+                self.sp -= 1;
+                self.pc = self.stack[self.sp as usize];
+                self.pc += 2;
+            }
 
             // 1NNN Jump to location
-            0x1000 => {
-                self.pc = nnn;
+            Instruction::Jump { addr } => {
+                self.pc = addr;
             }
 
             // 2NNN Call subroutine
-            0x2000 => {
+            Instruction::Call { addr } => {
+                if self.sp as usize >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { pc: self.pc });
+                }
                 self.stack[self.sp as usize] = self.pc;
 
                 self.sp = self.sp.wrapping_add(1);
-                self.pc = nnn;
+                self.pc = addr;
             }
 
             // 3XNN Skip next instruction if Vx = nn
-            0x3000 => {
-                if self.vreg[vx] == nn {
-                    self.pc += 4;
+            Instruction::SkipEqImm { x, nn } => {
+                if self.vreg[x] == nn {
+                    self.skip_next();
                 } else {
                     self.pc += 2;
                 }
             }
 
             // 4XNN Skip next instruction if Vx != nn
-            0x4000 => {
-                if self.vreg[vx] != nn {
-                    self.pc += 4;
+            Instruction::SkipNeImm { x, nn } => {
+                if self.vreg[x] != nn {
+                    self.skip_next();
                 } else {
                     self.pc += 2;
                 }
             }
 
             // 5XY0 Skip next instruction if Vx = Vy
-            0x5000 => {
-                if self.vreg[vx] == self.vreg[vy] {
-                    self.pc += 4;
+            Instruction::SkipEqReg { x, y } => {
+                if self.vreg[x] == self.vreg[y] {
+                    self.skip_next();
                 } else {
                     self.pc += 2;
                 }
             }
 
+            // 5XY2 Store registers Vx through Vy in memory starting at location I (XO-CHIP)
+            Instruction::SaveRange { x, y } => {
+                self.check_range(self.ireg as usize, x.abs_diff(y) + 1)?;
+                for (offset, reg) in Cpu::reg_range(x, y).enumerate() {
+                    self.memory[self.ireg as usize + offset] = self.vreg[reg];
+                }
+                self.pc += 2;
+            }
+
+            // 5XY3 Fill registers Vx through Vy from memory starting at location I (XO-CHIP)
+            Instruction::LoadRange { x, y } => {
+                self.check_range(self.ireg as usize, x.abs_diff(y) + 1)?;
+                for (offset, reg) in Cpu::reg_range(x, y).enumerate() {
+                    self.vreg[reg] = self.memory[self.ireg as usize + offset];
+                }
+                self.pc += 2;
+            }
+
             // 6XNN Set Vx = nn
-            0x6000 => {
-                self.vreg[vx] = nn;
+            Instruction::LoadImm { x, nn } => {
+                self.vreg[x] = nn;
                 self.pc += 2;
             }
 
             // 7XNN Set Vx = Vx + nn
-            0x7000 => {
-                self.vreg[vx] = self.vreg[vx].wrapping_add(nn);
+            Instruction::AddImm { x, nn } => {
+                self.vreg[x] = self.vreg[x].wrapping_add(nn);
                 self.pc += 2;
             }
 
-            // 9XE0 Skip next instruction if Vx != Vy
-            0x9000 => {
-                if self.vreg[vx] != self.vreg[vy] {
-                    self.pc += 4;
+            // 9XY0 Skip next instruction if Vx != Vy
+            Instruction::SkipNeReg { x, y } => {
+                if self.vreg[x] != self.vreg[y] {
+                    self.skip_next();
                 } else {
                     self.pc += 2;
                 }
             }
 
-            // DXYN
-            0xD000 => {
-                self.draw(display);
+            // DXYN Draw N-byte sprite at (Vx, Vy), or a 16x16 sprite if N = 0 (Super CHIP)
+            Instruction::Draw { x, y, n } => {
+                // With the display wait quirk, only one sprite is drawn per frame;
+                // the instruction is retried after the next vblank.
+                if self.quirks.display_wait && self.drawn {
+                    return Ok(());
+                }
+
+                self.draw(x, y, n)?;
+                self.drawn = true;
                 self.pc += 2;
             }
 
-            0x8000 => {
-                match self.opcode & 0x000F {
-                    /* Logical and arithmetic instructions */
-                    // 8XY0 Set Vx = Vy
-                    0x0000 => {
-                        self.vreg[vx] = self.vreg[vy];
-                        self.pc += 2;
-                    }
-
-                    // 8XY1 Set Vx = Vx | Vy (Binary OR)
-                    0x0001 => {
-                        self.vreg[vx] = self.vreg[vx] | self.vreg[vy];
-                        self.pc += 2;
-                    }
+            /* Logical and arithmetic instructions */
+            // 8XY0 Set Vx = Vy
+            Instruction::Move { x, y } => {
+                self.vreg[x] = self.vreg[y];
+                self.pc += 2;
+            }
 
-                    // 8XY2 Set Vx = Vx & Vy (Binary AND)
-                    0x0002 => {
-                        self.vreg[vx] = self.vreg[vx] & self.vreg[vy];
-                        self.pc += 2;
-                    }
+            // 8XY1 Set Vx = Vx | Vy (Binary OR)
+            Instruction::Or { x, y } => {
+                self.vreg[x] |= self.vreg[y];
+                if self.quirks.vf_reset {
+                    self.vreg[0xF] = 0;
+                }
+                self.pc += 2;
+            }
 
-                    // 8XY3 Set Vx = Vx ^ Vy (Binary XOR)
-                    0x0003 => {
-                        self.vreg[vx] = self.vreg[vx] ^ self.vreg[vy];
-                        self.pc += 2;
-                    }
+            // 8XY2 Set Vx = Vx & Vy (Binary AND)
+            Instruction::And { x, y } => {
+                self.vreg[x] &= self.vreg[y];
+                if self.quirks.vf_reset {
+                    self.vreg[0xF] = 0;
+                }
+                self.pc += 2;
+            }
 
-                    // 8XY4 Set Vx = Vx + Vy
-                    0x0004 => {
-                        self.vreg[vx] = self.vreg[vx].wrapping_add(self.vreg[vy]);
-                        self.pc += 2;
-                    }
+            // 8XY3 Set Vx = Vx ^ Vy (Binary XOR)
+            Instruction::Xor { x, y } => {
+                self.vreg[x] ^= self.vreg[y];
+                if self.quirks.vf_reset {
+                    self.vreg[0xF] = 0;
+                }
+                self.pc += 2;
+            }
 
-                    // 8XY5 Set Vx = Vx - Vy
-                    0x0005 => {
-                        self.vreg[vx] = self.vreg[vx].wrapping_sub(self.vreg[vy]);
-                        self.vreg[0xF] = if self.vreg[vy] > self.vreg[vx] { 0 } else { 1 };
-                        self.pc += 2;
-                    }
+            // 8XY4 Set Vx = Vx + Vy, VF = carry
+            // (the flag is written last, so it wins when Vx is VF)
+            Instruction::Add { x, y } => {
+                let (sum, carry) = self.vreg[x].overflowing_add(self.vreg[y]);
+                self.vreg[x] = sum;
+                self.vreg[0xF] = carry as u8;
+                self.pc += 2;
+            }
 
-                    // 8XY6 Set Vx = Vx >> 1 (WARNING: AMBIGUOUS)
-                    0x0006 => {
-                        self.vreg[0xF] = self.vreg[vx] & 1;
-                        self.vreg[vx] >>= 1;
-                        self.pc += 2;
-                    }
+            // 8XY5 Set Vx = Vx - Vy, VF = NOT borrow
+            Instruction::Sub { x, y } => {
+                let (diff, borrow) = self.vreg[x].overflowing_sub(self.vreg[y]);
+                self.vreg[x] = diff;
+                self.vreg[0xF] = !borrow as u8;
+                self.pc += 2;
+            }
 
-                    // 8XY7 Set Vx = Vy - Vx
-                    0x0007 => {
-                        self.vreg[vx] = self.vreg[vy].wrapping_sub(self.vreg[vx]);
-                        self.vreg[0xF] = if self.vreg[vx] > self.vreg[vy] { 0 } else { 1 };
-                        self.pc += 2;
-                    }
+            // 8XY6 Set Vx = Vx >> 1, or Vx = Vy >> 1 (quirks.shift_vy)
+            Instruction::ShiftRight { x, y } => {
+                let src = self.vreg[if self.quirks.shift_vy { y } else { x }];
+                self.vreg[x] = src >> 1;
+                self.vreg[0xF] = src & 1;
+                self.pc += 2;
+            }
 
-                    // 8XYE Set Vx = Vx << 1 (WARNING: AMBIGUOUS)
-                    0x000E => {
-                        self.vreg[0xF] = (self.vreg[vx] >> 7) & 1;
-                        self.vreg[vx] <<= 1;
-                        self.pc += 2;
-                    }
+            // 8XY7 Set Vx = Vy - Vx, VF = NOT borrow
+            Instruction::SubReverse { x, y } => {
+                let (diff, borrow) = self.vreg[y].overflowing_sub(self.vreg[x]);
+                self.vreg[x] = diff;
+                self.vreg[0xF] = !borrow as u8;
+                self.pc += 2;
+            }
 
-                    _ => println!("Unknown opcode: 8X{:X}", self.opcode),
-                }
+            // 8XYE Set Vx = Vx << 1, or Vx = Vy << 1 (quirks.shift_vy)
+            Instruction::ShiftLeft { x, y } => {
+                let src = self.vreg[if self.quirks.shift_vy { y } else { x }];
+                self.vreg[x] = src << 1;
+                self.vreg[0xF] = (src >> 7) & 1;
+                self.pc += 2;
             }
 
             // ANNN Set I = NNN
-            0xA000 => {
-                self.ireg = nnn;
+            Instruction::LoadI { addr } => {
+                self.ireg = addr;
                 self.pc += 2;
             }
 
-            // BNNN Jump to address NNN + V0 (WARNING: AMBIGUOUS)
-            0xB000 => {
-                self.pc = nnn + self.vreg[0] as u16;
+            // BNNN Jump to address NNN + V0, or XNN + Vx (quirks.jump_vx)
+            Instruction::JumpOffset { x, addr } => {
+                let offset = self.vreg[if self.quirks.jump_vx { x } else { 0 }];
+                self.pc = addr + offset as u16;
             }
 
             // CXNN Random
-            0xC000 => {
-                let mut rng = rand::thread_rng();
-                self.vreg[vx] = rng.gen::<u8>() & nn;
+            Instruction::Random { x, nn } => {
+                self.vreg[x] = self.rng.next_byte(&self.memory[..]) & nn;
                 self.pc += 2;
             }
 
             /* Skip if key */
-            0xE000 => {
-                match self.opcode & 0x00FF {
-                    // EX9E Skip next instruction if key with the value of Vx is pressed
-                    0x009E => {
-                        if self.keypad[self.vreg[vx] as usize] != 0 {
-                            self.pc += 4;
-                        } else {
-                            self.pc += 2;
-                        }
-                    }
-
-                    // EXA1 Skip next instruction if key with the value of Vx is not pressed
-                    0x00A1 => {
-                        if self.keypad[self.vreg[vx] as usize] == 0 {
-                            self.pc += 4;
-                        } else {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => println!("Unknown opcode: 00{:#X}", self.opcode),
+            // EX9E Skip next instruction if key with the value of Vx is pressed
+            // (only the low nibble of Vx selects the key, as on the VIP)
+            Instruction::SkipKey { x } => {
+                if self.keypad[(self.vreg[x] & 0xF) as usize] != 0 {
+                    self.skip_next();
+                } else {
+                    self.pc += 2;
                 }
             }
 
-            0xF000 => match self.opcode & 0x00FF {
-                // FX07 Set Vx = delay timer
-                0x0007 => {
-                    self.vreg[vx] = self.delay_timer;
+            // EXA1 Skip next instruction if key with the value of Vx is not pressed
+            Instruction::SkipNotKey { x } => {
+                if self.keypad[(self.vreg[x] & 0xF) as usize] == 0 {
+                    self.skip_next();
+                } else {
                     self.pc += 2;
                 }
+            }
 
-                // FX15 Set delay timer = Vx
-                0x0015 => {
-                    self.delay_timer = self.vreg[vx];
-                    self.pc += 2;
-                }
+            // F000 NNNN Set I = NNNN, the 16-bit word after this instruction (XO-CHIP)
+            Instruction::LoadLongI { addr } => {
+                self.ireg = addr;
+                self.pc += 4;
+            }
 
-                // FX18 Set sound timer = Vx
-                0x0018 => {
-                    self.sound_timer = self.vreg[vx];
-                    self.pc += 2;
-                }
+            // FN01 Select bitplanes N for drawing, clearing and scrolling (XO-CHIP)
+            Instruction::Plane { n } => {
+                self.planes = n & 0b11;
+                self.pc += 2;
+            }
+
+            // F002 Load the 16-byte audio pattern buffer from memory at I (XO-CHIP)
+            Instruction::Audio => {
+                let start = self.ireg as usize;
+                self.check_range(start, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[start..start + 16]);
+                self.audio_pattern = Some(pattern);
+                self.pc += 2;
+            }
+
+            // FX3A Set audio pattern playback pitch = Vx (XO-CHIP)
+            Instruction::Pitch { x } => {
+                self.pitch = self.vreg[x];
+                self.pc += 2;
+            }
+
+            // FX07 Set Vx = delay timer
+            Instruction::GetDelay { x } => {
+                self.vreg[x] = self.delay_timer;
+                self.pc += 2;
+            }
 
-                // FX1E Set I = I + Vx
-                0x001E => {
-                    self.ireg += self.vreg[vx] as u16;
+            // FX15 Set delay timer = Vx
+            Instruction::SetDelay { x } => {
+                self.delay_timer = self.vreg[x];
+                self.pc += 2;
+            }
+
+            // FX18 Set sound timer = Vx
+            Instruction::SetSound { x } => {
+                self.sound_timer = self.vreg[x];
+                self.pc += 2;
+            }
+
+            // FX1E Set I = I + Vx (VF is left alone unless the index_overflow quirk is set)
+            Instruction::AddI { x } => {
+                self.ireg = self.ireg.wrapping_add(self.vreg[x] as u16);
+                if self.quirks.index_overflow {
                     self.vreg[0xF] = (self.ireg > 0xFFF) as u8;
-                    self.pc += 2;
                 }
+                self.pc += 2;
+            }
 
-                // FX0A Block until a key is pressed
-                0x000A => {
-                    for i in 0..0xF {
-                        if self.keypad[i] != 0 {
-                            self.vreg[vx] = i as u8;
-                            break;
-                        }
+            // FX0A Block until a key is pressed and released, then set Vx = key.
+            // PC stays put while waiting, so timers keep running as normal.
+            Instruction::WaitKey { x } => match self.key_wait {
+                KeyWait::Idle | KeyWait::Press => {
+                    self.key_wait = match self.keypad.iter().position(|&key| key != 0) {
+                        Some(key) => KeyWait::Release(key as u8),
+                        None => KeyWait::Press,
+                    };
+                }
+                KeyWait::Release(key) => {
+                    if self.keypad[key as usize] == 0 {
+                        self.vreg[x] = key;
+                        self.key_wait = KeyWait::Idle;
+                        self.pc += 2;
                     }
-
-                    self.pc += 2;
                 }
+            },
 
-                // FX29 Set I = location of sprite for digit Vx
-                0x0029 => {
-                    self.ireg = (self.vreg[vx] as u16).wrapping_mul(0x5);
-                    self.pc += 2;
-                }
+            // FX29 Set I = location of sprite for digit Vx
+            // (only the low nibble selects the digit, as on the VIP, so I
+            // always points into the font)
+            Instruction::Font { x } => {
+                self.ireg = (self.vreg[x] & 0xF) as u16 * 5;
+                self.pc += 2;
+            }
 
-                // Fx33 Store BCD representation of Vx in memory locations I, I+1, and I+2
-                0x0033 => {
-                    self.memory[self.ireg as usize] = self.vreg[vx] / 100;
-                    self.memory[self.ireg as usize + 1] = (self.vreg[vx] % 100) / 10;
-                    self.memory[self.ireg as usize + 2] = self.vreg[vx] % 10;
-                    self.pc += 2;
-                }
+            // FX30 Set I = location of big sprite for digit Vx (Super CHIP)
+            Instruction::BigFont { x } => {
+                self.ireg = (BIG_FONT_OFFSET + (self.vreg[x] & 0xF) as usize * 10) as u16;
+                self.pc += 2;
+            }
 
-                // FX55 Store registers V0 through Vx in memory starting at location I
-                0x0055 => {
-                    for i in 0..=vx {
-                        self.memory[self.ireg as usize + i] = self.vreg[i];
-                    }
-                    self.pc += 2;
+            // Fx33 Store BCD representation of Vx in memory locations I, I+1, and I+2
+            Instruction::Bcd { x } => {
+                self.check_range(self.ireg as usize, 3)?;
+                self.memory[self.ireg as usize] = self.vreg[x] / 100;
+                self.memory[self.ireg as usize + 1] = (self.vreg[x] % 100) / 10;
+                self.memory[self.ireg as usize + 2] = self.vreg[x] % 10;
+                self.pc += 2;
+            }
+
+            // FX55 Store registers V0 through Vx in memory starting at location I
+            Instruction::Store { x } => {
+                self.check_range(self.ireg as usize, x + 1)?;
+                for i in 0..=x {
+                    self.memory[self.ireg as usize + i] = self.vreg[i];
                 }
+                if self.quirks.load_store_increment {
+                    self.ireg = self.ireg.wrapping_add(x as u16 + 1);
+                }
+                self.pc += 2;
+            }
 
-                // FX65 Fill registers V0 through Vx from memory starting at location I
-                0x0065 => {
-                    for i in 0..=vx {
-                        self.vreg[i] = self.memory[self.ireg as usize + i];
-                    }
-                    self.pc += 2;
+            // FX65 Fill registers V0 through Vx from memory starting at location I
+            Instruction::Load { x } => {
+                self.check_range(self.ireg as usize, x + 1)?;
+                for i in 0..=x {
+                    self.vreg[i] = self.memory[self.ireg as usize + i];
+                }
+                if self.quirks.load_store_increment {
+                    self.ireg = self.ireg.wrapping_add(x as u16 + 1);
                 }
+                self.pc += 2;
+            }
 
-                _ => println!("Unknown opcode: FX{:#X}", self.opcode),
-            },
-            _ => println!("Unknown opcode: {:#X}", self.opcode),
+            // FX75 Store V0 through Vx in RPL user flags (Super CHIP)
+            Instruction::SaveFlags { x } => {
+                self.rpl[..=x].copy_from_slice(&self.vreg[..=x]);
+                self.pc += 2;
+            }
+
+            // FX85 Read V0 through Vx from RPL user flags (Super CHIP)
+            Instruction::LoadFlags { x } => {
+                self.vreg[..=x].copy_from_slice(&self.rpl[..=x]);
+                self.pc += 2;
+            }
+
+            Instruction::Unknown { opcode } => {
+                return Err(Chip8Error::UnknownOpcode {
+                    pc: self.pc,
+                    opcode,
+                })
+            }
         }
+
+        Ok(())
     }
 
-    pub fn draw(&mut self, display: &mut Display) {
-        let sprite_w: usize = 8;
-        let sprite_h = (self.opcode & 0x000F) as usize;
-        let sprite_x = usize::from(self.vreg[((self.opcode & 0x0F00) >> 8) as usize]);
-        let sprite_y = usize::from(self.vreg[((self.opcode & 0x00F0) >> 4) as usize]);
+    /// Draws the sprite at I to (Vx, Vy); see DXYN.
+    fn draw(&mut self, x: usize, y: usize, n: u8) -> Result<(), Chip8Error> {
+        let n = n as usize;
+        // DXY0 draws a 16x16 sprite stored as 2 bytes per row
+        let (sprite_w, sprite_h) = if n == 0 { (16, 16) } else { (8, n) };
+        let sprite_x = usize::from(self.vreg[x]);
+        let sprite_y = usize::from(self.vreg[y]);
+        let width = self.pixels.width();
+        let height = self.pixels.height();
+        let sprite_bytes = sprite_h * sprite_w / 8;
+
+        self.check_range(
+            self.ireg as usize,
+            sprite_bytes * self.planes.count_ones() as usize,
+        )?;
 
         self.vreg[0xF] = 0;
 
-        for col in 0..sprite_h {
-            let pixel = self.memory[self.ireg as usize + col as usize] as u16;
+        // With several bitplanes selected, each plane's sprite data follows the previous one
+        let mut addr = self.ireg as usize;
+
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+
+            for col in 0..sprite_h {
+                let pixel = if sprite_w == 16 {
+                    let row_addr = addr + col * 2;
+                    (self.memory[row_addr] as u16) << 8 | self.memory[row_addr + 1] as u16
+                } else {
+                    self.memory[addr + col] as u16
+                };
+
+                for row in 0..sprite_w {
+                    let x = sprite_x % width + row;
+                    let y = sprite_y % height + col;
 
-            for row in 0..sprite_w {
-                let x = (sprite_x + row) % WIDTH;
-                let y = (sprite_y + col) % HEIGHT;
+                    if self.quirks.clip && (x >= width || y >= height) {
+                        continue;
+                    }
 
-                if pixel & if false { 0x8000 } else { 0x80 } >> row != 0 {
-                    self.vreg[0xF] |= self.pixels[y % HEIGHT][x % WIDTH] as u8;
-                    self.pixels[y][x] ^= true;
+                    if pixel & (1 << (sprite_w - 1 - row)) != 0 {
+                        self.vreg[0xF] |= self.pixels.toggle(x % width, y % height, plane) as u8;
+                    }
                 }
             }
+
+            addr += sprite_bytes;
         }
 
-        display.draw(&self.pixels);
+        self.redraw = true;
+        Ok(())
     }
 
-    pub fn reset(&mut self) {
-        println!("Resetting CPU");
+    /// Fails with a memory fault unless `len` bytes from `addr` are in memory.
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        if addr + len > self.memory.len() {
+            return Err(Chip8Error::MemoryFault {
+                pc: self.pc,
+                addr: addr.max(self.memory.len()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Moves PC past the instruction at PC without executing it.
+    pub fn skip_instruction(&mut self) {
+        let size = self.peek_instruction().size();
+        self.pc = self.pc.wrapping_add(size);
+    }
+
+    /// Advances past the current instruction and the next one.
+    /// The next instruction is 4 bytes long if it is F000 NNNN (XO-CHIP).
+    fn skip_next(&mut self) {
+        self.pc += 2;
+
+        let next = self.read_word(self.pc);
+
+        self.pc += if next == 0xF000 { 4 } else { 2 };
+    }
 
+    /// Registers Vx through Vy, in either direction (5XY2/5XY3).
+    fn reg_range(vx: usize, vy: usize) -> Box<dyn Iterator<Item = usize>> {
+        if vx <= vy {
+            Box::new(vx..=vy)
+        } else {
+            Box::new((vy..=vx).rev())
+        }
+    }
+
+    /// Signals the start of a new 60 Hz frame.
+    pub fn vblank(&mut self) {
+        self.drawn = false;
+    }
+
+    pub fn reset(&mut self) {
         for v in &mut self.vreg {
             *v = 0;
         }
@@ -393,32 +777,42 @@ impl Cpu {
         self.pc = 0x200;
         self.sp = 0;
         self.stack = [0; 16];
-        self.sttick = 0.0;
-        self.tick = 0.0;
+        self.cycles = 0;
+        self.drawn = false;
+        self.halted = false;
+        self.planes = 1;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.rng.reset();
+        self.key_wait = KeyWait::Idle;
 
         self.delay_timer = 0;
         self.sound_timer = 0;
 
-        self.pixels = [[false; WIDTH]; HEIGHT];
+        self.pixels = Framebuffer::lores();
+        self.redraw = true;
     }
 
-    pub fn update_timers(&mut self, dt: f32) {
-        if self.delay_timer > 0 {
-            self.tick -= dt;
-
-            if self.tick <= 0.0 {
-                self.delay_timer -= 1;
-                self.tick = 1.0 / 60.0;
-            }
-        }
+    /// Number of instructions to run in the next 60 Hz frame. Rates that
+    /// aren't a multiple of 60 carry the remainder over, so e.g. 500 Hz runs
+    /// frames of 8, 8 and 9 instructions.
+    pub fn frame_budget(&mut self) -> u32 {
+        let cycles = self.cycles as u64 + self.hz as u64;
+        self.cycles = (cycles % 60) as u32;
+        (cycles / 60) as u32
+    }
 
-        if self.sound_timer > 0 {
-            self.sttick -= dt;
+    /// Changes `hz` by `steps` quarter octaves (about 19% each), so every
+    /// step feels the same at any speed. Stays within MIN_HZ..=MAX_HZ.
+    pub fn adjust_hz(&mut self, steps: i32) -> u32 {
+        let hz = self.hz as f64 * 2f64.powf(steps as f64 / 4.0);
+        self.hz = (hz.round() as u32).clamp(MIN_HZ, MAX_HZ);
+        self.hz
+    }
 
-            if self.sttick <= 0.0 {
-                self.sound_timer -= 1;
-                self.sttick = 1.0 / 60.0;
-            }
-        }
+    /// Counts the delay and sound timers down by one 60 Hz tick.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 }
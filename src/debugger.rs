@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    cpu::Cpu,
+    disasm::{self, Labels, Syntax},
+    instruction::Instruction,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    Step,
+    StepFrame,
+    StepOver { return_pc: u16, sp: u8 },
+}
+
+/**
+ * Interactive debugger: PC breakpoints and stepping.
+ *
+ * `Machine::frame` asks the debugger before every instruction whether it
+ * may run (`should_run`) and reports back after it ran (`after_tick`), so
+ * execution can stop in the middle of a frame.
+ *
+ * With `trace` set it also records a disassembly line for every instruction
+ * it lets run; the frontend collects them with `take_trace`.
+ */
+pub struct Debugger {
+    pub breakpoints: BTreeSet<u16>,
+    pub trace: bool, // Record every instruction run (--debug)
+    mode: Mode,
+    resume_pc: Option<u16>, // Don't break on the breakpoint we just resumed from
+    trace_log: Vec<String>, // Trace lines not yet taken
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::with_trace(false)
+    }
+
+    pub fn with_trace(trace: bool) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            trace,
+            mode: Mode::Running,
+            resume_pc: None,
+            trace_log: Vec::new(),
+        }
+    }
+
+    /// True while execution is stopped (or about to stop after a step).
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    /// True while the debugger is in control, i.e. not freely running.
+    pub fn is_active(&self) -> bool {
+        self.mode != Mode::Running
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    /// Continues running until the next breakpoint.
+    pub fn resume(&mut self, cpu: &Cpu) {
+        self.start(Mode::Running, cpu);
+    }
+
+    /// Executes a single instruction, then pauses.
+    pub fn step(&mut self, cpu: &Cpu) {
+        self.start(Mode::Step, cpu);
+    }
+
+    /// Executes the rest of the current frame, then pauses.
+    pub fn step_frame(&mut self, cpu: &Cpu) {
+        self.start(Mode::StepFrame, cpu);
+    }
+
+    /// Like `step`, but runs a 2NNN subroutine call until it returns.
+    pub fn step_over(&mut self, cpu: &Cpu) {
+        if let Instruction::Call { .. } = cpu.peek_instruction() {
+            let mode = Mode::StepOver {
+                return_pc: cpu.pc() + 2,
+                sp: cpu.sp(),
+            };
+            self.start(mode, cpu);
+        } else {
+            self.step(cpu);
+        }
+    }
+
+    fn start(&mut self, mode: Mode, cpu: &Cpu) {
+        self.mode = mode;
+        self.resume_pc = Some(cpu.pc());
+    }
+
+    /// Adds a breakpoint at `pc`, or removes it if there already is one.
+    /// Returns true if the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, pc: u16) -> bool {
+        if self.breakpoints.remove(&pc) {
+            false
+        } else {
+            self.breakpoints.insert(pc);
+            true
+        }
+    }
+
+    /// Called before each instruction. Returns false if execution should stop.
+    pub fn should_run(&mut self, cpu: &Cpu) -> bool {
+        if self.mode == Mode::Paused {
+            return false;
+        }
+
+        let pc = cpu.pc();
+        if self.resume_pc.take() != Some(pc) && self.breakpoints.contains(&pc) {
+            self.mode = Mode::Paused;
+            return false;
+        }
+
+        if self.trace {
+            self.trace_log.push(format!(
+                "{:03X}: {:04X}  {}",
+                pc,
+                cpu.peek_opcode(),
+                disasm::mnemonic(&cpu.peek_instruction(), Syntax::Classic, &Labels::new())
+            ));
+        }
+
+        true
+    }
+
+    /// Trace lines recorded since the last call, oldest first.
+    pub fn take_trace(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.trace_log)
+    }
+
+    /// Called after each instruction.
+    pub fn after_tick(&mut self, cpu: &Cpu) {
+        match self.mode {
+            Mode::Step => self.mode = Mode::Paused,
+            Mode::StepOver { return_pc, sp } if cpu.pc() == return_pc && cpu.sp() == sp => {
+                self.mode = Mode::Paused
+            }
+            _ => {}
+        }
+    }
+
+    /// Called at the end of each frame.
+    pub fn end_frame(&mut self) {
+        if self.mode == Mode::StepFrame {
+            self.mode = Mode::Paused;
+        }
+    }
+
+    /// Register, stack and timer view.
+    pub fn view(&self, cpu: &Cpu) -> Vec<String> {
+        let vreg = cpu.vreg();
+        let regs = |range: std::ops::Range<usize>| {
+            range
+                .map(|i| format!("V{:X}={:02X}", i, vreg[i]))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let stack = cpu
+            .stack()
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        vec![
+            format!(
+                "PC={:03X} [{:04X}] {} I={:03X} SP={} {}",
+                cpu.pc(),
+                cpu.peek_opcode(),
+                disasm::mnemonic(&cpu.peek_instruction(), Syntax::Octo, &Labels::new()),
+                cpu.ireg(),
+                cpu.sp(),
+                if self.is_paused() {
+                    "PAUSED"
+                } else {
+                    "RUNNING"
+                },
+            ),
+            regs(0..8),
+            regs(8..16),
+            format!(
+                "DT={:02X} ST={:02X}{}",
+                cpu.delay_timer(),
+                cpu.sound_timer,
+                if cpu.waiting_for_key() {
+                    " WAITING FOR KEY"
+                } else {
+                    ""
+                }
+            ),
+            format!("Stack: {}", stack),
+            format!("Breakpoints: {}", breakpoints),
+        ]
+    }
+}
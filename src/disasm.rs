@@ -0,0 +1,296 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::instruction::Instruction;
+
+/// Names for addresses, used in place of raw jump and call targets.
+pub type Labels = BTreeMap<u16, String>;
+
+/**
+ * Assembly syntax for disassembler output.
+ *
+ * `Octo` output can be fed back to Octo (and `chipate asm`); `Classic` uses
+ * the Cowgod-style mnemonics most CHIP-8 references are written in.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Octo,
+    Classic,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Syntax, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "octo" => Ok(Syntax::Octo),
+            "classic" | "cowgod" => Ok(Syntax::Classic),
+            _ => Err(format!("Unknown syntax: {} (expected octo or classic)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Syntax::Octo => "octo",
+            Syntax::Classic => "classic",
+        })
+    }
+}
+
+/// One disassembled instruction, or trailing bytes too short to be one.
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>,
+}
+
+/// Decodes `rom`, loaded at `origin`, front to back. Data mixed in with code
+/// is decoded as if it were instructions.
+pub fn disassemble(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let word = |i: usize| match rom.get(i..i + 2) {
+            Some(bytes) => (bytes[0] as u16) << 8 | bytes[1] as u16,
+            None => 0,
+        };
+        let instruction = Instruction::decode(word(offset), word(offset + 2));
+        let len = instruction.size() as usize;
+
+        if offset + len > rom.len() {
+            lines.push(Line {
+                addr,
+                bytes: rom[offset..].to_vec(),
+                instruction: None,
+            });
+            break;
+        }
+
+        lines.push(Line {
+            addr,
+            bytes: rom[offset..offset + len].to_vec(),
+            instruction: Some(instruction),
+        });
+        offset += len;
+    }
+
+    lines
+}
+
+/// Labels for every jump and call target that lands on a decoded line.
+/// Subroutines are named `sub_NNN`, other targets `label_NNN`.
+pub fn find_labels(lines: &[Line]) -> Labels {
+    let mut labels = Labels::new();
+
+    for instruction in lines.iter().filter_map(|line| line.instruction) {
+        let Some(target) = instruction.target() else {
+            continue;
+        };
+        if !lines.iter().any(|line| line.addr == target) {
+            continue;
+        }
+
+        if let Instruction::Call { .. } = instruction {
+            labels.insert(target, format!("sub_{:03X}", target));
+        } else {
+            labels
+                .entry(target)
+                .or_insert_with(|| format!("label_{:03X}", target));
+        }
+    }
+
+    labels
+}
+
+/// Full listing of `rom` with addresses, raw bytes and labels.
+pub fn listing(rom: &[u8], origin: u16, syntax: Syntax) -> String {
+    let lines = disassemble(rom, origin);
+    let labels = find_labels(&lines);
+    let mut out = String::new();
+
+    for line in &lines {
+        let text = match line.instruction {
+            Some(instruction) => mnemonic(&instruction, syntax, &labels),
+            None => data(&line.bytes, syntax),
+        };
+        let bytes = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>();
+
+        match syntax {
+            Syntax::Octo => {
+                if let Some(label) = labels.get(&line.addr) {
+                    out += &format!(": {}\n", label);
+                }
+                out += &format!("\t{:<24} # {:04X}: {}\n", text, line.addr, bytes);
+            }
+            Syntax::Classic => {
+                if let Some(label) = labels.get(&line.addr) {
+                    out += &format!("{}:\n", label);
+                }
+                out += &format!("{:04X}  {:<8}  {}\n", line.addr, bytes, text);
+            }
+        }
+    }
+
+    out
+}
+
+/// Raw bytes that don't form an instruction.
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Octo => bytes
+            .iter()
+            .map(|byte| format!("0x{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Syntax::Classic => bytes
+            .iter()
+            .map(|byte| format!("DB #{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join("; "),
+    }
+}
+
+/// A single instruction in the given syntax. Jump and call targets are
+/// replaced by their label if `labels` has one.
+pub fn mnemonic(instruction: &Instruction, syntax: Syntax, labels: &Labels) -> String {
+    match syntax {
+        Syntax::Octo => octo(instruction, labels),
+        Syntax::Classic => classic(instruction, labels),
+    }
+}
+
+fn octo(instruction: &Instruction, labels: &Labels) -> String {
+    use Instruction::*;
+
+    let target = |addr: u16| match labels.get(&addr) {
+        Some(label) => label.clone(),
+        None => format!("0x{:03X}", addr),
+    };
+
+    match *instruction {
+        Clear => "clear".to_string(),
+        Return => "return".to_string(),
+        ScrollDown { n } => format!("scroll-down {}", n),
+        ScrollUp { n } => format!("scroll-up {}", n),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        Lores => "lores".to_string(),
+        Hires => "hires".to_string(),
+        Jump { addr } => format!("jump {}", target(addr)),
+        Call { addr } => format!(":call {}", target(addr)),
+        // Octo's `if ... then` runs the next instruction when the condition
+        // holds, so every skip reads as its negation.
+        SkipEqImm { x, nn } => format!("if v{:x} != 0x{:02X} then", x, nn),
+        SkipNeImm { x, nn } => format!("if v{:x} == 0x{:02X} then", x, nn),
+        SkipEqReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+        LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+        LoadImm { x, nn } => format!("v{:x} := 0x{:02X}", x, nn),
+        AddImm { x, nn } => format!("v{:x} += 0x{:02X}", x, nn),
+        Move { x, y } => format!("v{:x} := v{:x}", x, y),
+        Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        Add { x, y } => format!("v{:x} += v{:x}", x, y),
+        Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+        ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        SubReverse { x, y } => format!("v{:x} =- v{:x}", x, y),
+        ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        SkipNeReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        LoadI { addr } => format!("i := {}", target(addr)),
+        JumpOffset { addr, .. } => format!("jump0 {}", target(addr)),
+        Random { x, nn } => format!("v{:x} := random 0x{:02X}", x, nn),
+        Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        SkipKey { x } => format!("if v{:x} -key then", x),
+        SkipNotKey { x } => format!("if v{:x} key then", x),
+        LoadLongI { addr } => format!("i := long 0x{:04X}", addr),
+        Plane { n } => format!("plane {}", n),
+        Audio => "audio".to_string(),
+        GetDelay { x } => format!("v{:x} := delay", x),
+        WaitKey { x } => format!("v{:x} := key", x),
+        SetDelay { x } => format!("delay := v{:x}", x),
+        SetSound { x } => format!("buzzer := v{:x}", x),
+        AddI { x } => format!("i += v{:x}", x),
+        Font { x } => format!("i := hex v{:x}", x),
+        BigFont { x } => format!("i := bighex v{:x}", x),
+        Bcd { x } => format!("bcd v{:x}", x),
+        Pitch { x } => format!("pitch := v{:x}", x),
+        Store { x } => format!("save v{:x}", x),
+        Load { x } => format!("load v{:x}", x),
+        SaveFlags { x } => format!("saveflags v{:x}", x),
+        LoadFlags { x } => format!("loadflags v{:x}", x),
+        Unknown { opcode } => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
+
+fn classic(instruction: &Instruction, labels: &Labels) -> String {
+    use Instruction::*;
+
+    let target = |addr: u16| match labels.get(&addr) {
+        Some(label) => label.clone(),
+        None => format!("#{:03X}", addr),
+    };
+
+    match *instruction {
+        Clear => "CLS".to_string(),
+        Return => "RET".to_string(),
+        ScrollDown { n } => format!("SCD {}", n),
+        ScrollUp { n } => format!("SCU {}", n),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        Lores => "LOW".to_string(),
+        Hires => "HIGH".to_string(),
+        Jump { addr } => format!("JP {}", target(addr)),
+        Call { addr } => format!("CALL {}", target(addr)),
+        SkipEqImm { x, nn } => format!("SE V{:X}, #{:02X}", x, nn),
+        SkipNeImm { x, nn } => format!("SNE V{:X}, #{:02X}", x, nn),
+        SkipEqReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        SaveRange { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+        LoadRange { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+        LoadImm { x, nn } => format!("LD V{:X}, #{:02X}", x, nn),
+        AddImm { x, nn } => format!("ADD V{:X}, #{:02X}", x, nn),
+        Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        SubReverse { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        SkipNeReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        LoadI { addr } => format!("LD I, {}", target(addr)),
+        JumpOffset { addr, .. } => format!("JP V0, {}", target(addr)),
+        Random { x, nn } => format!("RND V{:X}, #{:02X}", x, nn),
+        Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        SkipKey { x } => format!("SKP V{:X}", x),
+        SkipNotKey { x } => format!("SKNP V{:X}", x),
+        LoadLongI { addr } => format!("LD I, #{:04X}", addr),
+        Plane { n } => format!("PLANE {}", n),
+        Audio => "AUDIO".to_string(),
+        GetDelay { x } => format!("LD V{:X}, DT", x),
+        WaitKey { x } => format!("LD V{:X}, K", x),
+        SetDelay { x } => format!("LD DT, V{:X}", x),
+        SetSound { x } => format!("LD ST, V{:X}", x),
+        AddI { x } => format!("ADD I, V{:X}", x),
+        Font { x } => format!("LD F, V{:X}", x),
+        BigFont { x } => format!("LD HF, V{:X}", x),
+        Bcd { x } => format!("LD B, V{:X}", x),
+        Pitch { x } => format!("PITCH V{:X}", x),
+        Store { x } => format!("LD [I], V{:X}", x),
+        Load { x } => format!("LD V{:X}, [I]", x),
+        SaveFlags { x } => format!("LD R, V{:X}", x),
+        LoadFlags { x } => format!("LD V{:X}, R", x),
+        Unknown { opcode } => format!("DW #{:04X}", opcode),
+    }
+}
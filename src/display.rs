@@ -1,46 +1,50 @@
+use crate::{
+    consts::*,
+    filter::{Filter, Persistence},
+    framebuffer::Framebuffer,
+    palette::Palette,
+    video::VideoSink,
+};
 
+use std::time::{Duration, Instant};
 
-use crate::consts::*;
-
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Texture;
-use sdl2::surface::{Surface};
-
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::ttf::Font;
+use sdl2::video::WindowContext;
 
 use sdl2::Sdl;
 
-pub struct Config<'a> {
+pub struct Config {
     pub fullscreen: bool,
     pub software_render: bool,
-    pub fg_hex: &'a str,
-    pub bg_hex: &'a str,
+    pub palette: Palette,
 }
 
-pub struct Display {
-    pub canvas: sdl2::render::WindowCanvas,
-    pub pixels: [[bool; WIDTH]; HEIGHT],
-    pub fg: Color,
-    pub bg: Color,
-    pub texture_creator: sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-    pub info: String,
-}
-
-fn hex_to_rgb(hex: &str) -> Result<(u8, u8, u8), ()> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
-        return Err(());
-    }
-
-    let r = u8::from_str_radix(&hex[0..2], 16).map_err(|_| ())?;
-    let g = u8::from_str_radix(&hex[2..4], 16).map_err(|_| ())?;
-    let b = u8::from_str_radix(&hex[4..6], 16).map_err(|_| ())?;
+/// How long a toast message stays on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(2);
 
-    Ok((r, g, b))
+/**
+ * The emulator window, before anything is drawn to it.
+ *
+ * Opening the window is separate from creating the `Display` because the
+ * display keeps a texture alive across frames, and SDL textures borrow the
+ * `TextureCreator` they were made with. The caller holds on to the creator:
+ *
+ * ```ignore
+ * let window = Window::open(&sdl_context, config)?;
+ * let texture_creator = window.texture_creator();
+ * let display = Display::new(window, &texture_creator)?;
+ * ```
+ */
+pub struct Window {
+    canvas: WindowCanvas,
+    palette: Palette,
 }
 
-impl Display {
-    pub fn new(sdl_context: &Sdl, config: Config) -> Result<Display, String> {
+impl Window {
+    pub fn open(sdl_context: &Sdl, config: Config) -> Result<Window, String> {
         let video_subsystem = sdl_context
             .video()
             .map_err(|_| "Failed to get video subsystem".to_string())?;
@@ -70,63 +74,268 @@ impl Display {
             .build()
             .map_err(|e| format!("Failed to create software rendered canvas: {}", e))?;
 
-        let background_color = hex_to_rgb(config.bg_hex).unwrap();
-        let foreground_color = hex_to_rgb(config.fg_hex).unwrap();
+        Ok(Window {
+            canvas,
+            palette: config.palette,
+        })
+    }
 
-        let texture_creator = canvas.texture_creator();
+    pub fn texture_creator(&self) -> TextureCreator<WindowContext> {
+        self.canvas.texture_creator()
+    }
+}
 
-        let display = Display {
-            canvas,
-            pixels: [[false; WIDTH]; HEIGHT],
-            bg: Color::RGB(background_color.0, background_color.1, background_color.2),
-            fg: Color::RGB(foreground_color.0, foreground_color.1, foreground_color.2),
+pub struct Display<'a> {
+    pub canvas: WindowCanvas,
+    pub texture_creator: &'a TextureCreator<WindowContext>,
+    pub font: Option<Font<'a, 'static>>, // On-screen text; None if no font could be loaded
+    pub info: String,                    // Status line shown by the overlay
+    pub overlay: bool,                   // Overlay visible (F5)
+    pub panel: Vec<String>,              // Extra lines under the status line (debugger view)
+    toasts: Vec<(String, Instant)>,      // Short-lived messages and when they expire
+    screen: Texture<'a>,                 // Streaming texture holding the last frame drawn
+    palette: Palette,                    // Colors for framebuffer pixel values
+    frame: Framebuffer,                  // Last frame drawn, to skip unchanged frames
+    persistence: Persistence,            // Blends frames before they go into `screen`
+}
+
+/// A streaming texture the size of the framebuffer.
+fn create_screen<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    width: usize,
+    height: usize,
+) -> Result<Texture<'a>, String> {
+    texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .map_err(|e| format!("Failed to create screen texture: {}", e))
+}
+
+impl<'a> Display<'a> {
+    pub fn new(
+        window: Window,
+        texture_creator: &'a TextureCreator<WindowContext>,
+    ) -> Result<Display<'a>, String> {
+        let frame = Framebuffer::lores();
+        let mut display = Display {
+            canvas: window.canvas,
+            palette: window.palette,
             texture_creator,
+            font: None,
             info: "--".to_string(),
+            overlay: false,
+            panel: Vec::new(),
+            toasts: Vec::new(),
+            screen: create_screen(texture_creator, frame.width(), frame.height())?,
+            frame,
+            persistence: Persistence::new(Filter::Raw),
         };
+        display.update();
 
         Ok(display)
     }
 
-    pub fn draw(&mut self, pixels: &[[bool; WIDTH]; HEIGHT]) {
-        let window_width = self.canvas.window().size().0;
-        let window_height = self.canvas.window().size().1;
-        let scale_factor =
-            std::cmp::min(window_width / WIDTH as u32, window_height / HEIGHT as u32);
+    /// Shows a short-lived message. It is also printed, since the overlay
+    /// may not be able to render text.
+    pub fn toast<S: Into<String>>(&mut self, message: S) {
+        let message = message.into();
+        println!("{}", message);
+        self.toasts.push((message, Instant::now() + TOAST_DURATION));
+    }
 
-        /* Draw screen to texture */
-        let mut surface = Surface::new(
-            WIDTH as u32,
-            HEIGHT as u32,
-            sdl2::pixels::PixelFormatEnum::RGB24,
-        )
-        .unwrap();
+    /// True while the overlay or a toast is showing and the window needs
+    /// redrawing every frame, even if the framebuffer did not change.
+    pub fn overlay_active(&self) -> bool {
+        self.overlay || !self.panel.is_empty() || !self.toasts.is_empty()
+    }
 
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let color = if pixels[y][x] { self.fg } else { self.bg };
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
 
-                surface
-                    .fill_rect(Rect::new((x as u32) as i32, (y as u32) as i32, 1, 1), color)
-                    .unwrap();
+    /// Switches palettes, recoloring the frame on screen.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.update();
+    }
+
+    /// The palette color for a pixel value, e.g. to draw menus in.
+    pub fn color(&self, pixel: u8) -> Color {
+        let [r, g, b] = self.palette.color(pixel);
+        Color::RGB(r, g, b)
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.persistence.filter()
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.persistence.set_filter(filter);
+    }
+
+    /// True while a filter is still blending in earlier frames and the
+    /// window needs redrawing every frame, even if the framebuffer did not
+    /// change.
+    pub fn animating(&self) -> bool {
+        !self.persistence.settled()
+    }
+
+    /// Redraws the last frame, e.g. to update the overlay.
+    pub fn refresh(&mut self) {
+        if self.animating() {
+            self.update();
+        }
+        self.render();
+    }
+
+    pub fn draw(&mut self, pixels: &Framebuffer) {
+        if *pixels != self.frame {
+            if (pixels.width(), pixels.height()) != (self.frame.width(), self.frame.height()) {
+                // Only happens when a SUPER-CHIP program switches resolution
+                match create_screen(self.texture_creator, pixels.width(), pixels.height()) {
+                    Ok(screen) => self.screen = screen,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                }
             }
+
+            self.frame.clone_from(pixels);
+            self.update();
+        } else if self.animating() {
+            self.update();
+        }
+
+        self.render();
+    }
+
+    /// Runs `frame` through the filter and into the screen texture.
+    fn update(&mut self) {
+        self.persistence.push(&self.frame, &self.palette.table());
+
+        if let Err(e) = self.upload() {
+            println!("Failed to update screen texture: {}", e);
         }
+    }
+
+    /// Copies the filter output into the screen texture.
+    fn upload(&mut self) -> Result<(), String> {
+        let rgb = self.persistence.rgb();
+        let row_len = self.frame.width() * 3;
+
+        self.screen.with_lock(None, |buffer, pitch| {
+            for (row, pixels) in buffer.chunks_mut(pitch).zip(rgb.chunks_exact(row_len)) {
+                row[..row_len].copy_from_slice(pixels);
+            }
+        })
+    }
+
+    /// Scales the screen texture to the window, draws the overlay on top
+    /// and presents.
+    fn render(&mut self) {
+        let (window_width, window_height) = self.canvas.window().size();
+        let width = self.frame.width() as u32;
+        let height = self.frame.height() as u32;
+        let scale_factor = std::cmp::min(window_width / width, window_height / height);
 
-        let texture = Texture::from_surface(&surface, &self.texture_creator).unwrap();
-        let src = Rect::new(0, 0, WIDTH as u32, HEIGHT as u32);
         let dst = Rect::new(
-            (window_width / 2) as i32 - ((WIDTH as u32 * scale_factor) / 2) as i32,
-            (window_height / 2) as i32 - ((HEIGHT as u32 * scale_factor) / 2) as i32,
-            WIDTH as u32 * scale_factor,
-            HEIGHT as u32 * scale_factor,
+            (window_width / 2) as i32 - ((width * scale_factor) / 2) as i32,
+            (window_height / 2) as i32 - ((height * scale_factor) / 2) as i32,
+            width * scale_factor,
+            height * scale_factor,
         );
 
         self.canvas.set_draw_color(Color::BLACK);
-        self.canvas
-            .fill_rect(Rect::new(0, 0, window_width, window_height))
-            .unwrap();
+        self.canvas.clear();
 
         /* Copy texture to window */
-        self.canvas.copy(&texture, src, dst).unwrap();
+        self.canvas.copy(&self.screen, None, dst).unwrap();
+
+        if let Err(e) = self.draw_overlay() {
+            println!("Failed to draw overlay: {}", e);
+        }
+
         self.canvas.present();
     }
+
+    fn draw_overlay(&mut self) -> Result<(), String> {
+        let now = Instant::now();
+        self.toasts.retain(|(_, expires)| *expires > now);
+
+        let line_height = match &self.font {
+            Some(font) => font.recommended_line_spacing(),
+            None => return Ok(()),
+        };
+        let (_, window_height) = self.canvas.window().size();
+
+        let mut lines = Vec::new();
+        if self.overlay {
+            lines.push(self.info.clone());
+        }
+        lines.extend(self.panel.iter().cloned());
+
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text_boxed(line, 4, 4 + line_height * i as i32)?;
+        }
+
+        // Toasts stack up from the bottom, newest last
+        let toasts: Vec<String> = self.toasts.iter().map(|(m, _)| m.clone()).collect();
+        for (i, message) in toasts.iter().enumerate() {
+            let y = window_height as i32 - 4 - line_height * (toasts.len() - i) as i32;
+            self.draw_text_boxed(message, 4, y)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws text over a translucent box so it stays readable on any frame.
+    fn draw_text_boxed(&mut self, text: &str, x: i32, y: i32) -> Result<(), String> {
+        let (width, height) = match &self.font {
+            Some(font) => font.size_of(text).map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas.fill_rect(Rect::new(x, y, width + 8, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        self.draw_text(text, x + 4, y, Color::WHITE)?;
+        Ok(())
+    }
+
+    /// Draws a line of text with its top left corner at (x, y).
+    /// Returns the size of the rendered text.
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: i32,
+        y: i32,
+        color: Color,
+    ) -> Result<(u32, u32), String> {
+        let font = self.font.as_ref().ok_or("No font loaded")?;
+
+        if text.is_empty() {
+            return Ok((0, 0));
+        }
+
+        let surface = font
+            .render(text)
+            .blended(color)
+            .map_err(|e| e.to_string())?;
+        let texture =
+            Texture::from_surface(&surface, self.texture_creator).map_err(|e| e.to_string())?;
+        let (width, height) = (surface.width(), surface.height());
+
+        self.canvas
+            .copy(&texture, None, Rect::new(x, y, width, height))?;
+
+        Ok((width, height))
+    }
+}
+
+impl VideoSink for Display<'_> {
+    fn present(&mut self, pixels: &Framebuffer) {
+        self.draw(pixels);
+    }
 }
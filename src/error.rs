@@ -0,0 +1,109 @@
+use std::{fmt, str::FromStr};
+
+/**
+ * Errors from loading programs and configuration, and faults raised by the
+ * CPU while running a program.
+ *
+ * Faults (`is_fault`) leave the CPU exactly as it was before the faulting
+ * instruction; what happens next is up to the `FaultPolicy`.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    Io { path: String, message: String },
+    RomTooLarge { size: usize, max: usize },
+    Keymap { path: String, message: String },
+    Palette { path: String, message: String },
+    InvalidColor(String),
+
+    UnknownOpcode { pc: u16, opcode: u16 },
+    MemoryFault { pc: u16, addr: usize }, // Access past the end of memory
+    StackOverflow { pc: u16 },            // 2NNN with a full stack
+    StackUnderflow { pc: u16 },           // 00EE with an empty stack
+}
+
+impl Chip8Error {
+    /// True for errors raised by a running program.
+    pub fn is_fault(&self) -> bool {
+        self.pc().is_some()
+    }
+
+    /// Address of the faulting instruction.
+    pub fn pc(&self) -> Option<u16> {
+        match *self {
+            Chip8Error::UnknownOpcode { pc, .. }
+            | Chip8Error::MemoryFault { pc, .. }
+            | Chip8Error::StackOverflow { pc }
+            | Chip8Error::StackUnderflow { pc } => Some(pc),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::Io { path, message } => write!(f, "{}: {}", path, message),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is too large, size: {} > {}", size, max)
+            }
+            Chip8Error::Keymap { path, message } => {
+                write!(f, "Failed to parse keymap file: {} ({})", path, message)
+            }
+            Chip8Error::Palette { path, message } => {
+                write!(f, "Failed to parse palette file: {} ({})", path, message)
+            }
+            Chip8Error::InvalidColor(hex) => {
+                write!(f, "Invalid color: {} (expected #RRGGBB or #RGB)", hex)
+            }
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            Chip8Error::MemoryFault { pc, addr } => {
+                write!(
+                    f,
+                    "Memory fault at {:03X}: address {:X} is out of range",
+                    pc, addr
+                )
+            }
+            Chip8Error::StackOverflow { pc } => write!(f, "Stack overflow at {:03X}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "Stack underflow at {:03X}", pc),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// What `Machine` does when the CPU faults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaultPolicy {
+    #[default]
+    Halt, // Stop the CPU until it is reset
+    Skip, // Skip the faulting instruction and carry on
+    Trap, // Pause in the debugger at the faulting instruction
+}
+
+impl FromStr for FaultPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FaultPolicy, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "halt" => Ok(FaultPolicy::Halt),
+            "skip" => Ok(FaultPolicy::Skip),
+            "trap" | "debug" => Ok(FaultPolicy::Trap),
+            _ => Err(format!(
+                "Unknown fault policy: {} (expected halt, skip or trap)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FaultPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FaultPolicy::Halt => "halt",
+            FaultPolicy::Skip => "skip",
+            FaultPolicy::Trap => "trap",
+        })
+    }
+}
@@ -0,0 +1,174 @@
+use std::{fmt, str::FromStr};
+
+use crate::framebuffer::Framebuffer;
+
+/// Fraction of a pixel's glow left one frame after it is switched off.
+const PHOSPHOR_DECAY: f32 = 0.6;
+
+/// How successive frames are combined before they are shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Raw, // Every frame exactly as drawn
+    Blend,    // OR of the last two frames
+    Phosphor, // Switched off pixels fade out like a CRT
+}
+
+impl Filter {
+    /// The next filter, for cycling through them with a hotkey.
+    pub fn next(self) -> Filter {
+        match self {
+            Filter::Raw => Filter::Blend,
+            Filter::Blend => Filter::Phosphor,
+            Filter::Phosphor => Filter::Raw,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" | "none" => Ok(Filter::Raw),
+            "blend" | "or" => Ok(Filter::Blend),
+            "phosphor" | "decay" => Ok(Filter::Phosphor),
+            _ => Err(format!(
+                "Unknown filter: {} (expected raw, blend or phosphor)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Filter::Raw => "raw",
+            Filter::Blend => "blend",
+            Filter::Phosphor => "phosphor",
+        })
+    }
+}
+
+/**
+ * Turns frames into RGB images, blending each with the ones before it.
+ *
+ * CHIP-8 programs move sprites by XORing them off and drawing them again,
+ * so a frame often catches an object half erased. `Blend` shows a pixel if
+ * it was set in either of the last two frames, which hides most of that
+ * flicker. `Phosphor` lights pixels instantly and lets them fade over a few
+ * frames, the way the VIP's TV did.
+ *
+ * Call `push` once per displayed frame, including frames where nothing was
+ * drawn, until `settled` returns true.
+ */
+pub struct Persistence {
+    filter: Filter,
+    previous: Framebuffer, // Frame pushed before the last one
+    glow: Vec<[f32; 3]>,   // Phosphor: current color of every pixel
+    rgb: Vec<u8>,          // Output image
+    settled: bool,         // Pushing the same frame again would not change the output
+}
+
+impl Persistence {
+    pub fn new(filter: Filter) -> Persistence {
+        Persistence {
+            filter,
+            previous: Framebuffer::new(0, 0),
+            glow: Vec::new(),
+            rgb: Vec::new(),
+            settled: false,
+        }
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Switches filters. The next frame pushed is shown unblended.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.previous = Framebuffer::new(0, 0);
+        self.settled = false;
+    }
+
+    /// True once the output shows the last frame exactly, so pushing it
+    /// again can be skipped.
+    pub fn settled(&self) -> bool {
+        self.settled
+    }
+
+    /// The last output image, as RGB triplets row by row.
+    pub fn rgb(&self) -> &[u8] {
+        &self.rgb
+    }
+
+    /// Blends in the next frame, colored with `palette`.
+    pub fn push(&mut self, pixels: &Framebuffer, palette: &[[u8; 3]]) {
+        let resized =
+            (pixels.width(), pixels.height()) != (self.previous.width(), self.previous.height());
+        let len = pixels.width() * pixels.height();
+        self.rgb.resize(len * 3, 0);
+
+        match self.filter {
+            Filter::Raw => {
+                colorize(&mut self.rgb, pixels.rows().flatten().copied(), palette);
+                self.settled = true;
+            }
+            Filter::Blend if resized => {
+                colorize(&mut self.rgb, pixels.rows().flatten().copied(), palette);
+                self.settled = false;
+            }
+            Filter::Blend => {
+                let current = pixels.rows().flatten();
+                let previous = self.previous.rows().flatten();
+                let blended = current.zip(previous).map(|(&a, &b)| a | b);
+                colorize(&mut self.rgb, blended, palette);
+                self.settled = *pixels == self.previous;
+            }
+            Filter::Phosphor => {
+                let background = palette[0].map(f32::from);
+                if resized || self.glow.len() != len {
+                    self.glow.clear();
+                    self.glow.resize(len, background);
+                }
+
+                let mut settled = true;
+                for ((glow, rgb), &pixel) in self
+                    .glow
+                    .iter_mut()
+                    .zip(self.rgb.chunks_exact_mut(3))
+                    .zip(pixels.rows().flatten())
+                {
+                    if pixel != 0 {
+                        *glow = palette[pixel as usize % palette.len()].map(f32::from);
+                    } else {
+                        for (channel, bg) in glow.iter_mut().zip(background) {
+                            *channel = bg + (*channel - bg) * PHOSPHOR_DECAY;
+                            if (*channel - bg).abs() < 1.0 {
+                                *channel = bg;
+                            } else {
+                                settled = false;
+                            }
+                        }
+                    }
+
+                    for (out, channel) in rgb.iter_mut().zip(*glow) {
+                        *out = channel.round() as u8;
+                    }
+                }
+                self.settled = settled;
+            }
+        }
+
+        self.previous.clone_from(pixels);
+    }
+}
+
+/// Writes the palette color of every pixel into `rgb`.
+fn colorize(rgb: &mut [u8], pixels: impl Iterator<Item = u8>, palette: &[[u8; 3]]) {
+    for (rgb, pixel) in rgb.chunks_exact_mut(3).zip(pixels) {
+        rgb.copy_from_slice(&palette[pixel as usize % palette.len()]);
+    }
+}
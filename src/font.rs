@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use sdl2::ttf::{Font, Sdl2TtfContext};
+
+/// Fonts tried, in order, when no `--font` is given.
+const FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationMono-Regular.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/System/Library/Fonts/Monaco.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\consola.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+pub const FONT_SIZE: u16 = 16;
+
+/**
+ * Loads the font used for on-screen text (ROM browser, overlay).
+ *
+ * Uses `path` if given, otherwise the first common system font that exists.
+ * Returns None if no font could be loaded; text UI is then unavailable.
+ */
+pub fn load<'ttf>(ttf: &'ttf Sdl2TtfContext, path: Option<&str>) -> Option<Font<'ttf, 'static>> {
+    if let Some(path) = path {
+        return match ttf.load_font(path, FONT_SIZE) {
+            Ok(font) => Some(font),
+            Err(e) => {
+                println!("Failed to load font {}: {}", path, e);
+                None
+            }
+        };
+    }
+
+    FONT_PATHS
+        .iter()
+        .filter(|path| Path::new(path).exists())
+        .find_map(|path| ttf.load_font(path, FONT_SIZE).ok())
+}
@@ -0,0 +1,169 @@
+use crate::{consts::*, state};
+
+#[cfg(feature = "savestate")]
+use serde::{Deserialize, Serialize};
+
+/**
+ * Framebuffer whose resolution can change at runtime.
+ *
+ * CHIP-8 programs start in the 64x32 low resolution mode; SUPER-CHIP
+ * programs can switch to 128x64 with 00FF and back with 00FE.
+ *
+ * Each pixel is a bitmask of the XO-CHIP bitplanes it is set in (bit 0 for
+ * plane 1, bit 1 for plane 2), which doubles as its palette index. Plain
+ * CHIP-8 and SUPER-CHIP programs only ever use plane 1.
+ */
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "savestate", derive(Serialize, Deserialize))]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// A blank 64x32 framebuffer.
+    pub fn lores() -> Framebuffer {
+        Framebuffer::new(WIDTH, HEIGHT)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    /// Returns the palette index of a pixel.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u8) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    /// Flips a pixel in the given plane(s), returning true if it was set
+    /// before (a collision).
+    pub fn toggle(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        let pixel = &mut self.pixels[y * self.width + x];
+        let was_set = *pixel & planes != 0;
+        *pixel ^= planes;
+        was_set
+    }
+
+    /// Clears the given plane(s).
+    pub fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    /// True if the framebuffer is 64x32 or 128x64 with a pixel for every
+    /// position, e.g. after loading one from a file.
+    pub fn is_valid(&self) -> bool {
+        matches!(
+            (self.width, self.height),
+            (WIDTH, HEIGHT) | (HIRES_WIDTH, HIRES_HEIGHT)
+        ) && self.pixels.len() == self.width * self.height
+    }
+
+    /// Changes the resolution. The contents are cleared.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, u8> {
+        self.pixels.chunks(self.width)
+    }
+
+    /// Hash of the resolution and pixels; equal frames have equal hashes.
+    pub fn hash(&self) -> u64 {
+        let mut bytes = Vec::with_capacity(8 + self.pixels.len());
+        bytes.extend_from_slice(&(self.width as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.pixels);
+        state::fnv1a(&bytes)
+    }
+
+    /// Moves the given plane(s) by `dx` columns and `dy` rows, shifting in
+    /// blank pixels. Used to implement the scroll instructions.
+    fn shift(&mut self, dx: isize, dy: isize, planes: u8) {
+        let old = self.pixels.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let inside = (0..self.width as isize).contains(&src_x)
+                    && (0..self.height as isize).contains(&src_y);
+                let moved = if inside {
+                    old[src_y as usize * self.width + src_x as usize] & planes
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = (*pixel & !planes) | moved;
+            }
+        }
+    }
+
+    /// Scrolls the given plane(s) down by `n` rows.
+    pub fn scroll_down(&mut self, n: usize, planes: u8) {
+        self.shift(0, n as isize, planes);
+    }
+
+    /// Scrolls the given plane(s) up by `n` rows.
+    pub fn scroll_up(&mut self, n: usize, planes: u8) {
+        self.shift(0, -(n as isize), planes);
+    }
+
+    /// Scrolls the given plane(s) right by `n` columns.
+    pub fn scroll_right(&mut self, n: usize, planes: u8) {
+        self.shift(n as isize, 0, planes);
+    }
+
+    /// Scrolls the given plane(s) left by `n` columns.
+    pub fn scroll_left(&mut self, n: usize, planes: u8) {
+        self.shift(-(n as isize), 0, planes);
+    }
+}
+
+impl Clone for Framebuffer {
+    fn clone(&self) -> Framebuffer {
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.clone(),
+        }
+    }
+
+    // Reuses the pixel buffer, so copying a frame every frame doesn't allocate
+    fn clone_from(&mut self, source: &Framebuffer) {
+        self.width = source.width;
+        self.height = source.height;
+        self.pixels.clone_from(&source.pixels);
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::lores()
+    }
+}
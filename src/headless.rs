@@ -0,0 +1,187 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    chip8::Machine,
+    error::Chip8Error,
+    instruction::Instruction,
+    video::{NullSink, VideoSink},
+};
+
+/// A key going down or up at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub down: bool,
+}
+
+/**
+ * Scripted keypad input for windowless runs.
+ *
+ * One event per line: the frame number, the key (hex digit 0-F) and `down`
+ * or `up`. Blank lines and `#` comments are ignored:
+ *
+ * ```text
+ * # Start the game, then hold left for a second
+ * 30 5 down
+ * 32 5 up
+ * 60 4 down
+ * 120 4 up
+ * ```
+ */
+#[derive(Clone, Debug, Default)]
+pub struct InputScript {
+    events: Vec<KeyEvent>,
+    next: usize,
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputScript, String> {
+        let mut events = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let event = match fields[..] {
+                [frame, key, state] => {
+                    let frame = frame
+                        .parse()
+                        .map_err(|_| format!("line {}: invalid frame {}", i + 1, frame))?;
+                    let key = u8::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|&key| key < 16)
+                        .ok_or_else(|| format!("line {}: invalid key {}", i + 1, key))?;
+                    let down = match state {
+                        "down" => true,
+                        "up" => false,
+                        _ => return Err(format!("line {}: expected down or up", i + 1)),
+                    };
+                    KeyEvent { frame, key, down }
+                }
+                _ => return Err(format!("line {}: expected <frame> <key> <down|up>", i + 1)),
+            };
+            events.push(event);
+        }
+
+        events.sort_by_key(|event| event.frame);
+        Ok(InputScript { events, next: 0 })
+    }
+}
+
+impl fmt::Display for InputScript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            let state = if event.down { "down" } else { "up" };
+            writeln!(f, "{} {:X} {}", event.frame, event.key, state)?;
+        }
+        Ok(())
+    }
+}
+
+impl InputScript {
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    /// Appends an event. Events must be pushed in frame order.
+    pub fn push(&mut self, event: KeyEvent) {
+        self.events.push(event);
+    }
+
+    /// Applies the events for `frame` to the keypad.
+    pub fn apply(&mut self, frame: u32, keypad: &mut [u8; 16]) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+
+            keypad[event.key as usize] = event.down as u8;
+            self.next += 1;
+        }
+    }
+}
+
+/// Why a headless run stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Frames,            // Ran the requested number of frames
+    Loop { pc: u16 },  // Reached a jump to itself, the usual "end of program"
+    Exit,              // 00FD
+    Fault(Chip8Error), // The CPU faulted
+}
+
+impl Stop {
+    pub fn is_fault(&self) -> bool {
+        matches!(self, Stop::Fault(_))
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Frames => write!(f, "frame limit reached"),
+            Stop::Loop { pc } => write!(f, "halted in a loop at {:03X}", pc),
+            Stop::Exit => write!(f, "exited"),
+            Stop::Fault(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Result of a headless run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub frames: u32,
+    pub stop: Stop,
+}
+
+/// Runs `machine` for up to `frames` frames with no window, sound or real
+/// time, feeding it `input`. Stops early on a halt loop, 00FD or a fault.
+pub fn run(machine: &mut Machine, frames: u32, input: &mut InputScript) -> Report {
+    run_with_sink(machine, frames, input, &mut NullSink)
+}
+
+/// Like `run`, handing every emulated frame to `sink` as it finishes,
+/// whether or not anything was drawn. Frames held back by the debugger are
+/// left out.
+pub fn run_with_sink<S: VideoSink + ?Sized>(
+    machine: &mut Machine,
+    frames: u32,
+    input: &mut InputScript,
+    sink: &mut S,
+) -> Report {
+    for frame in 0..frames {
+        input.apply(frame, machine.keypad_mut());
+        let result = machine.frame();
+        if machine.frame_completed() {
+            sink.present(machine.pixels());
+        }
+
+        let pc = machine.cpu.pc();
+        let stop = match result {
+            Err(e) => Some(Stop::Fault(e)),
+            Ok(()) if machine.cpu.halted => Some(Stop::Exit),
+            Ok(()) => match machine.cpu.peek_instruction() {
+                Instruction::Jump { addr } if addr == pc => Some(Stop::Loop { pc }),
+                _ => None,
+            },
+        };
+
+        if let Some(stop) = stop {
+            return Report {
+                frames: frame + 1,
+                stop,
+            };
+        }
+    }
+
+    Report {
+        frames,
+        stop: Stop::Frames,
+    }
+}
@@ -0,0 +1,183 @@
+use std::{path::Path, str::FromStr};
+
+use crate::framebuffer::Framebuffer;
+
+/// Image file formats a framebuffer can be written as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Pbm,
+    Png,
+    Ascii,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "pbm" => Ok(Format::Pbm),
+            "png" => Ok(Format::Png),
+            "ascii" | "txt" => Ok(Format::Ascii),
+            _ => Err(format!(
+                "Unknown image format: {} (expected pbm, png or ascii)",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format from a file extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    /// Encodes `pixels` in this format.
+    pub fn encode(&self, pixels: &Framebuffer, palette: &[[u8; 3]]) -> Vec<u8> {
+        match self {
+            Format::Pbm => pbm(pixels),
+            Format::Png => png(pixels, palette),
+            Format::Ascii => ascii(pixels).into_bytes(),
+        }
+    }
+}
+
+/// Grayscale colors for palette indices 0-3, used when no palette is given.
+pub const MONO: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+/// Characters for palette indices 0-3 in `ascii` output.
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+/// Plain (P1) PBM image. A pixel is black if it is set in any plane.
+pub fn pbm(pixels: &Framebuffer) -> Vec<u8> {
+    let mut out = format!("P1\n{} {}\n", pixels.width(), pixels.height());
+
+    for row in pixels.rows() {
+        let line: Vec<&str> = row
+            .iter()
+            .map(|&pixel| if pixel != 0 { "1" } else { "0" })
+            .collect();
+        out += &line.join(" ");
+        out.push('\n');
+    }
+
+    out.into_bytes()
+}
+
+/// One character per pixel, one line per row.
+pub fn ascii(pixels: &Framebuffer) -> String {
+    pixels
+        .rows()
+        .map(|row| {
+            let mut line: String = row.iter().map(|&pixel| ASCII[pixel as usize & 3]).collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+/// RGB triplets for every pixel, looked up in `palette` by palette index.
+pub fn rgb(pixels: &Framebuffer, palette: &[[u8; 3]]) -> Vec<u8> {
+    pixels
+        .rows()
+        .flatten()
+        .flat_map(|&pixel| palette[pixel as usize % palette.len()])
+        .collect()
+}
+
+/// A copy of `pixels` enlarged `scale` times in each direction.
+pub fn scale(pixels: &Framebuffer, scale: usize) -> Framebuffer {
+    let mut scaled = Framebuffer::new(pixels.width() * scale, pixels.height() * scale);
+    for y in 0..scaled.height() {
+        for x in 0..scaled.width() {
+            scaled.set(x, y, pixels.get(x / scale, y / scale));
+        }
+    }
+    scaled
+}
+
+/// PNG image of the framebuffer, colored with `palette`.
+pub fn png(pixels: &Framebuffer, palette: &[[u8; 3]]) -> Vec<u8> {
+    encode_png(pixels.width(), pixels.height(), &rgb(pixels, palette))
+}
+
+/**
+ * Minimal PNG encoder for 8-bit RGB images.
+ *
+ * The image data is stored with no filtering in uncompressed deflate blocks.
+ * Chip-8 frames are tiny, so this is simpler than pulling in a compressor and
+ * still produces files every viewer can open.
+ */
+pub fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3) {
+        raw.push(0); // Filter type: none
+        raw.extend_from_slice(row);
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &ihdr);
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(kind.iter().chain(data));
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of stored (uncompressed) blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,226 @@
+/**
+ * A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+ *
+ * `x` and `y` are register indices, `nn` an 8-bit immediate, `n` a 4-bit
+ * immediate and `addr` a 12-bit (or, for `LoadLongI`, 16-bit) address.
+ * The interpreter and the disassembler both use `Instruction::decode`, so
+ * they always agree on what a word means.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Clear,                              // 00E0
+    Return,                             // 00EE
+    ScrollDown { n: u8 },               // 00CN (Super CHIP)
+    ScrollUp { n: u8 },                 // 00DN (XO-CHIP)
+    ScrollRight,                        // 00FB (Super CHIP)
+    ScrollLeft,                         // 00FC (Super CHIP)
+    Exit,                               // 00FD (Super CHIP)
+    Lores,                              // 00FE (Super CHIP)
+    Hires,                              // 00FF (Super CHIP)
+    Jump { addr: u16 },                 // 1NNN
+    Call { addr: u16 },                 // 2NNN
+    SkipEqImm { x: usize, nn: u8 },     // 3XNN
+    SkipNeImm { x: usize, nn: u8 },     // 4XNN
+    SkipEqReg { x: usize, y: usize },   // 5XY0
+    SaveRange { x: usize, y: usize },   // 5XY2 (XO-CHIP)
+    LoadRange { x: usize, y: usize },   // 5XY3 (XO-CHIP)
+    LoadImm { x: usize, nn: u8 },       // 6XNN
+    AddImm { x: usize, nn: u8 },        // 7XNN
+    Move { x: usize, y: usize },        // 8XY0
+    Or { x: usize, y: usize },          // 8XY1
+    And { x: usize, y: usize },         // 8XY2
+    Xor { x: usize, y: usize },         // 8XY3
+    Add { x: usize, y: usize },         // 8XY4
+    Sub { x: usize, y: usize },         // 8XY5
+    ShiftRight { x: usize, y: usize },  // 8XY6
+    SubReverse { x: usize, y: usize },  // 8XY7
+    ShiftLeft { x: usize, y: usize },   // 8XYE
+    SkipNeReg { x: usize, y: usize },   // 9XY0
+    LoadI { addr: u16 },                // ANNN
+    JumpOffset { x: usize, addr: u16 }, // BNNN
+    Random { x: usize, nn: u8 },        // CXNN
+    Draw { x: usize, y: usize, n: u8 }, // DXYN
+    SkipKey { x: usize },               // EX9E
+    SkipNotKey { x: usize },            // EXA1
+    LoadLongI { addr: u16 },            // F000 NNNN (XO-CHIP)
+    Plane { n: u8 },                    // FN01 (XO-CHIP)
+    Audio,                              // F002 (XO-CHIP)
+    GetDelay { x: usize },              // FX07
+    WaitKey { x: usize },               // FX0A
+    SetDelay { x: usize },              // FX15
+    SetSound { x: usize },              // FX18
+    AddI { x: usize },                  // FX1E
+    Font { x: usize },                  // FX29
+    BigFont { x: usize },               // FX30 (Super CHIP)
+    Bcd { x: usize },                   // FX33
+    Pitch { x: usize },                 // FX3A (XO-CHIP)
+    Store { x: usize },                 // FX55
+    Load { x: usize },                  // FX65
+    SaveFlags { x: usize },             // FX75 (Super CHIP)
+    LoadFlags { x: usize },             // FX85 (Super CHIP)
+    Unknown { opcode: u16 },
+}
+
+impl Instruction {
+    /// Decodes `opcode`. `next` is the word after it, only used by the
+    /// 4-byte F000 NNNN instruction.
+    pub fn decode(opcode: u16, next: u16) -> Instruction {
+        use Instruction::*;
+
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let addr = opcode & 0x0FFF;
+
+        match opcode & 0xF000 {
+            0x0000 => match opcode & 0x0FFF {
+                0x00E0 => Clear,
+                0x00EE => Return,
+                0x00FB => ScrollRight,
+                0x00FC => ScrollLeft,
+                0x00FD => Exit,
+                0x00FE => Lores,
+                0x00FF => Hires,
+                op if op & 0x0FF0 == 0x00C0 => ScrollDown { n },
+                op if op & 0x0FF0 == 0x00D0 => ScrollUp { n },
+                _ => Unknown { opcode },
+            },
+            0x1000 => Jump { addr },
+            0x2000 => Call { addr },
+            0x3000 => SkipEqImm { x, nn },
+            0x4000 => SkipNeImm { x, nn },
+            0x5000 => match n {
+                0x0 => SkipEqReg { x, y },
+                0x2 => SaveRange { x, y },
+                0x3 => LoadRange { x, y },
+                _ => Unknown { opcode },
+            },
+            0x6000 => LoadImm { x, nn },
+            0x7000 => AddImm { x, nn },
+            0x8000 => match n {
+                0x0 => Move { x, y },
+                0x1 => Or { x, y },
+                0x2 => And { x, y },
+                0x3 => Xor { x, y },
+                0x4 => Add { x, y },
+                0x5 => Sub { x, y },
+                0x6 => ShiftRight { x, y },
+                0x7 => SubReverse { x, y },
+                0xE => ShiftLeft { x, y },
+                _ => Unknown { opcode },
+            },
+            0x9000 if n == 0 => SkipNeReg { x, y },
+            0xA000 => LoadI { addr },
+            0xB000 => JumpOffset { x, addr },
+            0xC000 => Random { x, nn },
+            0xD000 => Draw { x, y, n },
+            0xE000 => match nn {
+                0x9E => SkipKey { x },
+                0xA1 => SkipNotKey { x },
+                _ => Unknown { opcode },
+            },
+            0xF000 => match nn {
+                0x00 if x == 0 => LoadLongI { addr: next },
+                0x01 => Plane { n: x as u8 },
+                0x02 if x == 0 => Audio,
+                0x07 => GetDelay { x },
+                0x0A => WaitKey { x },
+                0x15 => SetDelay { x },
+                0x18 => SetSound { x },
+                0x1E => AddI { x },
+                0x29 => Font { x },
+                0x30 => BigFont { x },
+                0x33 => Bcd { x },
+                0x3A => Pitch { x },
+                0x55 => Store { x },
+                0x65 => Load { x },
+                0x75 => SaveFlags { x },
+                0x85 => LoadFlags { x },
+                _ => Unknown { opcode },
+            },
+            _ => Unknown { opcode },
+        }
+    }
+
+    /// Encodes the instruction back into its opcode bytes; the inverse of `decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        use Instruction::*;
+
+        let xy = |op: u16, x: usize, y: usize| op | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |op: u16, x: usize, nn: u8| op | (x as u16) << 8 | nn as u16;
+
+        let opcode = match *self {
+            Clear => 0x00E0,
+            Return => 0x00EE,
+            ScrollDown { n } => 0x00C0 | (n & 0xF) as u16,
+            ScrollUp { n } => 0x00D0 | (n & 0xF) as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump { addr } => 0x1000 | addr & 0xFFF,
+            Call { addr } => 0x2000 | addr & 0xFFF,
+            SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            SkipEqReg { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LoadImm { x, nn } => xnn(0x6000, x, nn),
+            AddImm { x, nn } => xnn(0x7000, x, nn),
+            Move { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            Add { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            ShiftRight { x, y } => xy(0x8006, x, y),
+            SubReverse { x, y } => xy(0x8007, x, y),
+            ShiftLeft { x, y } => xy(0x800E, x, y),
+            SkipNeReg { x, y } => xy(0x9000, x, y),
+            LoadI { addr } => 0xA000 | addr & 0xFFF,
+            JumpOffset { x, addr } => 0xB000 | (x as u16) << 8 | addr & 0xFFF,
+            Random { x, nn } => xnn(0xC000, x, nn),
+            Draw { x, y, n } => xy(0xD000, x, y) | (n & 0xF) as u16,
+            SkipKey { x } => xnn(0xE000, x, 0x9E),
+            SkipNotKey { x } => xnn(0xE000, x, 0xA1),
+            LoadLongI { addr } => return vec![0xF0, 0x00, (addr >> 8) as u8, addr as u8],
+            Plane { n } => xnn(0xF000, (n & 0xF) as usize, 0x01),
+            Audio => 0xF002,
+            GetDelay { x } => xnn(0xF000, x, 0x07),
+            WaitKey { x } => xnn(0xF000, x, 0x0A),
+            SetDelay { x } => xnn(0xF000, x, 0x15),
+            SetSound { x } => xnn(0xF000, x, 0x18),
+            AddI { x } => xnn(0xF000, x, 0x1E),
+            Font { x } => xnn(0xF000, x, 0x29),
+            BigFont { x } => xnn(0xF000, x, 0x30),
+            Bcd { x } => xnn(0xF000, x, 0x33),
+            Pitch { x } => xnn(0xF000, x, 0x3A),
+            Store { x } => xnn(0xF000, x, 0x55),
+            Load { x } => xnn(0xF000, x, 0x65),
+            SaveFlags { x } => xnn(0xF000, x, 0x75),
+            LoadFlags { x } => xnn(0xF000, x, 0x85),
+            Unknown { opcode } => opcode,
+        };
+
+        vec![(opcode >> 8) as u8, opcode as u8]
+    }
+
+    /// Length in bytes: 4 for F000 NNNN, 2 for everything else.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongI { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address this instruction jumps or calls to, if any.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump { addr } | Instruction::Call { addr } => Some(addr),
+            Instruction::JumpOffset { addr, .. } => Some(addr),
+            _ => None,
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use std::{fs::File, io::Read, path::Path};
+#[cfg(feature = "keymaps")]
+use crate::error::Chip8Error;
+#[cfg(feature = "keymaps")]
+use std::{fs, path::Path};
 
 /**
  * Keymap parsing
@@ -23,18 +26,50 @@ pub struct Keymap {
 }
 
 #[cfg(feature = "keymaps")]
-pub fn parse_keymap_file(keymap_file: &Path) -> Vec<Keymap> {
-    let mut file = File::open(keymap_file).unwrap();
+pub fn parse_keymap_file(keymap_file: &Path) -> Result<Vec<Keymap>, Chip8Error> {
+    let path = keymap_file.display().to_string();
+
+    let contents = fs::read_to_string(keymap_file).map_err(|e| Chip8Error::Io {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
+
+    let keymap: Vec<Keymap> = serde_json::from_str(&contents).map_err(|e| Chip8Error::Keymap {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
 
-    let mut contents = String::new();
+    // Catch bad entries here rather than when the keypad is read
+    for entry in &keymap {
+        let problem = if entry.key > 0xF {
+            Some(format!("key {} is not a keypad key (0-15)", entry.key))
+        } else if !valid_scancode(entry.scancode) {
+            Some(format!("unknown scancode {}", entry.scancode))
+        } else {
+            None
+        };
 
-    file.read_to_string(&mut contents).unwrap();
+        if let Some(problem) = problem {
+            return Err(Chip8Error::Keymap {
+                path,
+                message: format!(
+                    "{} in entry {{ \"scancode\": {}, \"key\": {} }}",
+                    problem, entry.scancode, entry.key
+                ),
+            });
+        }
+    }
+
+    Ok(keymap)
+}
+
+#[cfg(all(feature = "keymaps", feature = "sdl"))]
+fn valid_scancode(scancode: i32) -> bool {
+    sdl2::keyboard::Scancode::from_i32(scancode).is_some()
+}
 
-    serde_json::from_str(&contents).expect(
-        format!(
-            "Failed to parse keymap file: {}",
-            keymap_file.to_str().unwrap()
-        )
-        .as_str(),
-    )
+// Without SDL the keymap is never used to read keys
+#[cfg(all(feature = "keymaps", not(feature = "sdl")))]
+fn valid_scancode(_scancode: i32) -> bool {
+    true
 }
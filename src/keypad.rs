@@ -1,7 +1,7 @@
 extern crate sdl2;
 
 use sdl2::event::Event;
-use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
+use sdl2::keyboard::{KeyboardState, Keycode, Mod, Scancode};
 
 use sdl2::EventPump;
 use sdl2::Sdl;
@@ -18,11 +18,55 @@ pub enum State {
     Continue,
     Increase,
     Decrease,
-    Debug,
+    Debug, // Break into / continue from the debugger
+    Step,
+    StepOver,
+    StepFrame,
+    ToggleBreakpoint,
     Reset,
     PauseToggle,
     Pause,
     Unpause,
+    SaveState,
+    LoadState,
+    SelectSlot(u8),
+    Rewind(bool),      // Rewind hotkey held (true) or released (false)
+    FastForward(bool), // Fast-forward hotkey held (true) or released (false)
+    TurboToggle,
+    OpenRom,
+    OverlayToggle,
+    NextFilter,
+    NextPalette,
+    Screenshot,
+    VideoToggle,
+}
+
+/// Navigation input while a menu (the ROM browser) is open.
+pub enum Menu {
+    None,
+    Up,
+    Down,
+    Select,
+    Back,
+    Close,
+    Exit,
+}
+
+/// Maps the number row to save state slots.
+fn slot_key(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num0 => Some(0),
+        Keycode::Num1 => Some(1),
+        Keycode::Num2 => Some(2),
+        Keycode::Num3 => Some(3),
+        Keycode::Num4 => Some(4),
+        Keycode::Num5 => Some(5),
+        Keycode::Num6 => Some(6),
+        Keycode::Num7 => Some(7),
+        Keycode::Num8 => Some(8),
+        Keycode::Num9 => Some(9),
+        _ => None,
+    }
 }
 
 impl Keypad {
@@ -34,8 +78,10 @@ impl Keypad {
     }
 
     pub fn key_press(&mut self, key: &mut [u8; 16]) -> State {
+        let mut state = State::Continue;
+
         for event in self.pump.poll_iter() {
-            return match event {
+            let event_state = match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
@@ -53,6 +99,22 @@ impl Keypad {
                     keycode: Some(Keycode::F12),
                     ..
                 } => State::Debug,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => State::Step,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => State::StepOver,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => State::StepFrame,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => State::ToggleBreakpoint,
                 Event::KeyDown {
                     keycode: Some(Keycode::F3),
                     ..
@@ -61,21 +123,122 @@ impl Keypad {
                     keycode: Some(Keycode::F8),
                     ..
                 } => State::PauseToggle,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => State::OpenRom,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => State::OverlayToggle,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => State::NextFilter,
+                Event::KeyDown {
+                    keycode: Some(Keycode::PrintScreen),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => State::VideoToggle,
+                Event::KeyDown {
+                    keycode: Some(Keycode::PrintScreen),
+                    ..
+                } => State::Screenshot,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => State::SaveState,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => State::LoadState,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+                    && slot_key(keycode).is_some() =>
+                {
+                    State::SelectSlot(slot_key(keycode).unwrap())
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => State::NextPalette,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    repeat: false,
+                    ..
+                } => State::Rewind(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => State::Rewind(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    repeat: false,
+                    ..
+                } => State::FastForward(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => State::FastForward(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backquote),
+                    ..
+                } => State::TurboToggle,
                 Event::AppWillEnterBackground { .. } => State::Pause,
                 Event::AppWillEnterForeground { .. } => State::Unpause,
                 _ => State::Continue,
             };
+
+            if !matches!(event_state, State::Continue) {
+                state = event_state;
+            }
         }
 
-        let key_state = KeyboardState::new(&mut self.pump);
+        let key_state = KeyboardState::new(&self.pump);
+
+        // Keymap files are checked when loaded; skip anything that slipped by
+        for keymap in &self.keymap {
+            if let (Some(key), Some(scancode)) = (
+                key.get_mut(keymap.key as usize),
+                Scancode::from_i32(keymap.scancode),
+            ) {
+                *key = key_state.is_scancode_pressed(scancode) as u8;
+            }
+        }
+
+        state
+    }
+
+    /// Polls input for an open menu instead of the Chip-8 keypad.
+    pub fn menu_press(&mut self) -> Menu {
+        let mut menu = Menu::None;
+
+        for event in self.pump.poll_iter() {
+            let event_menu = match event {
+                Event::Quit { .. } => Menu::Exit,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => match keycode {
+                    Keycode::Up => Menu::Up,
+                    Keycode::Down => Menu::Down,
+                    Keycode::Return | Keycode::KpEnter => Menu::Select,
+                    Keycode::Backspace | Keycode::Left => Menu::Back,
+                    Keycode::Escape | Keycode::F4 => Menu::Close,
+                    _ => Menu::None,
+                },
+                _ => Menu::None,
+            };
 
-        for keymap in <Vec<Keymap> as Clone>::clone(&self.keymap).into_iter() {
-            key[keymap.key as usize] = key_state.is_scancode_pressed(
-                Scancode::from_i32(keymap.scancode)
-                    .expect(format!("Invalid scancode: {}", keymap.scancode).as_str()),
-            ) as u8;
+            if !matches!(event_menu, Menu::None) {
+                menu = event_menu;
+            }
         }
 
-        State::Continue
+        menu
     }
 }
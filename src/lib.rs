@@ -0,0 +1,48 @@
+//! ChipAte emulator core.
+//!
+//! The interpreter (`cpu`, `chip8`) has no SDL dependency and can be used on
+//! its own with `default-features = false`. The SDL frontend (`display`,
+//! `keypad`, `sound`) is only built with the `sdl` feature.
+
+pub mod assembler;
+pub mod capture;
+pub mod chip8;
+pub mod consts;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod filter;
+pub mod framebuffer;
+pub mod headless;
+pub mod image;
+pub mod instruction;
+pub mod keymaps;
+pub mod movie;
+pub mod palette;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod screenshot;
+pub mod state;
+pub mod video;
+
+#[cfg(feature = "sdl")]
+pub mod browser;
+#[cfg(feature = "sdl")]
+pub mod display;
+#[cfg(feature = "sdl")]
+pub mod font;
+#[cfg(feature = "sdl")]
+pub mod keypad;
+#[cfg(feature = "sdl")]
+pub mod sound;
+
+pub use chip8::Machine;
+pub use error::{Chip8Error, FaultPolicy};
+pub use framebuffer::Framebuffer;
+pub use instruction::Instruction;
+pub use palette::Palette;
+pub use quirks::{Platform, Quirks};
+pub use rng::{Rng, RngMode};
+pub use video::VideoSink;
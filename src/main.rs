@@ -1,29 +1,231 @@
-use std::path::Path;
+use std::{env, fs, path::Path, process};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use crate::{consts::STANDARD_KEYMAP, cpu::Cpu, keypad::Keypad, sound::Sound};
+use chipate::{
+    assembler,
+    capture::{VideoFormat, VideoRecorder},
+    consts::{DEFAULT_HZ, MAX_HZ, MIN_HZ, XO_MAX_ROM_SIZE},
+    disasm::{self, Syntax},
+    filter::Filter,
+    headless::{self, InputScript},
+    image::{self, Format},
+    movie::Movie,
+    palette::Palette,
+    screenshot::Screenshots,
+    FaultPolicy, Machine, Platform, RngMode,
+};
 
-mod consts;
-mod cpu;
-mod display;
-mod keymaps;
-mod keypad;
-mod sound;
+// The windowed frontend; `disasm`, `asm` and `headless` work without SDL
+#[cfg(all(feature = "sdl", feature = "keymaps"))]
+use chipate::keymaps;
+#[cfg(feature = "sdl")]
+use chipate::{
+    browser::RomBrowser,
+    consts::STANDARD_KEYMAP,
+    display, font,
+    keypad::{self, Keypad},
+    movie::{Player, Recorder},
+    palette::parse_color,
+    rewind::Rewind,
+    sound::Sound,
+    Chip8Error, VideoSink,
+};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Disassemble a ROM
+    Disasm {
+        #[arg()]
+        filename: String,
+
+        #[arg(
+            long,
+            default_value_t = Syntax::Octo,
+            help = "Output syntax (octo, classic)"
+        )]
+        syntax: Syntax,
+
+        #[arg(
+            long,
+            value_parser = parse_addr,
+            default_value = "200",
+            help = "Address the ROM is loaded at (hex)"
+        )]
+        origin: u16,
+
+        #[arg(short, long, help = "Write the listing to a file instead of stdout")]
+        output: Option<String>,
+    },
+
+    /// Assemble an Octo (.8o) source file
+    Asm {
+        #[arg()]
+        filename: String,
+
+        #[arg(
+            short,
+            long,
+            help = "Output ROM (default: the source with a .ch8 extension)"
+        )]
+        output: Option<String>,
+
+        #[arg(long, help = "Symbol map (default: the source with a .sym extension)")]
+        symbols: Option<String>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Run the ROM after assembling it"
+        )]
+        run: bool,
+    },
+
+    /// Run a ROM without a window or sound, e.g. in CI
+    Headless {
+        #[arg()]
+        filename: String,
+
+        #[arg(long, default_value_t = 600, help = "Maximum number of frames to run")]
+        frames: u32,
+
+        #[arg(
+            long,
+            default_value_t = DEFAULT_HZ,
+            value_parser = parse_hz,
+            help = "Instructions per second"
+        )]
+        hz: u32,
+
+        #[arg(
+            short,
+            long,
+            default_value_t = Platform::Vip,
+            help = "Platform quirk profile (vip, schip, xochip)"
+        )]
+        platform: Platform,
+
+        #[arg(long, help = "Seed for CXNN random numbers (default: random)")]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            default_value_t = RngMode::SplitMix,
+            help = "Random number generator (splitmix, vip)"
+        )]
+        rng: RngMode,
+
+        #[arg(long, help = "Keypad input script (lines of: <frame> <key> <down|up>)")]
+        input: Option<String>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["input", "seed", "rng"],
+            help = "Play back a recorded movie, with its settings and length"
+        )]
+        movie: Option<String>,
+
+        #[arg(short, long, help = "Write the final framebuffer to this file")]
+        output: Option<String>,
+
+        #[arg(
+            long,
+            help = "Output format (pbm, png, ascii; default: from the extension)"
+        )]
+        format: Option<Format>,
+
+        #[arg(
+            long,
+            default_value = "mono",
+            help = "Palette for PNG output: a palette file or a preset name"
+        )]
+        palette: String,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Save the final framebuffer as a timestamped PNG"
+        )]
+        screenshot: bool,
+
+        #[arg(long, default_value = ".", help = "Directory to save screenshots in")]
+        screenshot_dir: String,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=64),
+            help = "Also save screenshots enlarged this many times"
+        )]
+        screenshot_scale: u32,
+
+        #[arg(long, help = "Record every frame to a video file (.gif or .y4m)")]
+        video: Option<String>,
+
+        #[arg(long, help = "Video format (gif, y4m; default: from the extension)")]
+        video_format: Option<VideoFormat>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Print the final framebuffer as ASCII"
+        )]
+        ascii: bool,
+    },
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    #[arg()]
-    filename: String,
+    #[arg(required = true)]
+    filename: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_HZ,
+        value_parser = parse_hz,
+        help = "Instructions per second"
+    )]
+    hz: u32,
+
+    #[arg(
+        long,
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u32).range(2..=64),
+        help = "Speed multiplier while fast-forward (Tab) is held"
+    )]
+    fast_forward: u32,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Start in turbo mode: run as fast as the host allows"
+    )]
+    turbo: bool,
 
     #[arg(
         short,
         long,
-        default_value_t = 8,
-        help = "CPU speed (speed * 60 = Hz/TPS)"
+        default_value_t = Platform::Vip,
+        help = "Platform quirk profile (vip, schip, xochip)"
     )]
-    speed: u8,
+    platform: Platform,
 
     #[cfg(feature = "keymaps")]
     #[arg(long, help = "Keymap Filename")]
@@ -32,11 +234,31 @@ struct Args {
     #[arg(short, long, default_value_t = false, help = "Enable fullscreen mode")]
     fullscreen: bool,
 
-    #[arg(long, default_value_t = String::from("#0f380f"), help = "Background color")]
-    bg: String,
+    #[arg(
+        long,
+        default_value = "gameboy",
+        help = "Color palette: a palette file or one of gameboy, amber, mono, vip, octo, contrast, colorblind"
+    )]
+    palette: String,
+
+    #[arg(long, help = "Background color, overriding the palette")]
+    bg: Option<String>,
+
+    #[arg(long, help = "Foreground color, overriding the palette")]
+    fg: Option<String>,
+
+    #[arg(long, help = "XO-CHIP plane 2 color, overriding the palette")]
+    fg2: Option<String>,
 
-    #[arg(long, default_value_t = String::from("#8bac0f"), help = "Foreground color")]
-    fg: String,
+    #[arg(long, help = "XO-CHIP blended planes color, overriding the palette")]
+    blend: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = Filter::Raw,
+        help = "Display filter against flicker (raw, blend, phosphor)"
+    )]
+    filter: Filter,
 
     #[arg(
         long = "software",
@@ -45,23 +267,434 @@ struct Args {
     )]
     software_render: bool,
 
-    #[arg(long, default_value_t = false, help = "Enable debug mode")]
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print every executed instruction"
+    )]
     debug: bool,
+
+    #[arg(
+        long = "break",
+        value_parser = parse_addr,
+        help = "Break into the debugger at this address (hex, repeatable)"
+    )]
+    breakpoints: Vec<u16>,
+
+    #[arg(long, help = "TTF font for on-screen text (default: a system font)")]
+    font: Option<String>,
+
+    #[arg(
+        long,
+        default_value = ".",
+        help = "Directory to save screenshots and videos in"
+    )]
+    screenshot_dir: String,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=64),
+        help = "Also save screenshots enlarged this many times"
+    )]
+    screenshot_scale: u32,
+
+    #[arg(long, help = "Record a video from startup (.gif or .y4m)")]
+    record_video: Option<String>,
+
+    #[arg(
+        long,
+        help = "Video format (gif, y4m; default: from the extension, gif for the hotkey)"
+    )]
+    video_format: Option<VideoFormat>,
+
+    #[arg(
+        long,
+        default_value_t = 30,
+        help = "Seconds of rewind history to keep (0 to disable)"
+    )]
+    rewind: u32,
+
+    #[arg(long, default_value_t = 1, help = "Frames between rewind snapshots")]
+    rewind_interval: u32,
+
+    #[arg(
+        long,
+        default_value_t = 64,
+        help = "Maximum memory used by rewind history, in MiB"
+    )]
+    rewind_memory: usize,
+
+    #[arg(
+        long,
+        default_value_t = FaultPolicy::Halt,
+        help = "What to do when the ROM faults (halt, skip, trap)"
+    )]
+    on_fault: FaultPolicy,
+
+    #[arg(long, help = "Seed for CXNN random numbers (default: random)")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = RngMode::SplitMix,
+        help = "Random number generator (splitmix, vip)"
+    )]
+    rng: RngMode,
+
+    #[arg(long, help = "Record keypad input to a movie file")]
+    record: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["record", "seed", "rng"],
+        help = "Play back a movie file recorded with --record (it sets the RNG)"
+    )]
+    play: Option<String>,
+}
+
+/// Save state file for a slot, next to the ROM (e.g. `pong.ch8.st1`).
+#[cfg(all(feature = "sdl", feature = "savestate"))]
+fn state_path(rom_file: &str, slot: u8) -> String {
+    format!("{}.st{}", rom_file, slot)
+}
+
+/// Parses an address such as `0x2A0`, `$2A0` or `2a0` (always hex).
+fn parse_addr(s: &str) -> Result<u16, String> {
+    let hex = s.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid address {}: {}", s, e))
+}
+
+/// Parses an instruction rate, which must be within MIN_HZ..=MAX_HZ.
+fn parse_hz(s: &str) -> Result<u32, String> {
+    let hz: u32 = s
+        .parse()
+        .map_err(|e| format!("Invalid rate {}: {}", s, e))?;
+    if !(MIN_HZ..=MAX_HZ).contains(&hz) {
+        return Err(format!("Rate must be between {} and {} Hz", MIN_HZ, MAX_HZ));
+    }
+    Ok(hz)
+}
+
+/// Seeds the CXNN generator, picking a random seed if none was given. The
+/// seed is printed so a run can be reproduced with `--seed`.
+fn seed_rng(machine: &mut Machine, seed: Option<u64>, mode: RngMode) {
+    let seed = seed.unwrap_or_else(rand::random);
+    machine.cpu.seed_rng(seed, mode);
+    println!("RNG seed: {} ({})", seed, mode);
+}
+
+/// The palette from `--palette`, with any colors given on the command line
+/// (background, foreground, plane 2, blend) replacing its first entries.
+#[cfg(feature = "sdl")]
+fn build_palette(name: &str, overrides: [&Option<String>; 4]) -> Result<Palette, String> {
+    let mut palette = Palette::find(name)?;
+
+    for (index, hex) in overrides.into_iter().enumerate() {
+        if let Some(hex) = hex {
+            palette.set(index, parse_color(hex).map_err(|e| e.to_string())?);
+            palette.name = "custom".to_string();
+        }
+    }
+    Ok(palette)
+}
+
+/// ROM file name without directory or extension, for naming screenshots.
+fn rom_stem(file_name: &str) -> String {
+    Path::new(file_name)
+        .file_stem()
+        .map_or(file_name.to_string(), |stem| {
+            stem.to_string_lossy().into_owned()
+        })
+}
+
+/// ROM file name without its directory, for display.
+#[cfg(feature = "sdl")]
+fn rom_name(file_name: &str) -> String {
+    Path::new(file_name)
+        .file_name()
+        .map_or(file_name.to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        })
 }
 
 fn main() {
-    let args = Args::parse();
-    let file_name = args.filename;
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Some(Command::Disasm {
+            filename,
+            syntax,
+            origin,
+            output,
+        }) => disasm(&filename, syntax, origin, output.as_deref()),
+        Some(Command::Asm {
+            filename,
+            output,
+            symbols,
+            run: launch,
+        }) => asm(&filename, output, symbols).and_then(|(rom_file, breakpoints)| {
+            if !launch {
+                return Ok(());
+            }
+
+            // Run with the default emulator settings
+            let mut args = Cli::parse_from([env!("CARGO_PKG_NAME"), &rom_file]).args;
+            args.breakpoints.extend(breakpoints);
+            run(args)
+        }),
+        Some(Command::Headless {
+            filename,
+            frames,
+            hz,
+            platform,
+            seed,
+            rng,
+            input,
+            movie,
+            output,
+            format,
+            palette,
+            screenshot,
+            screenshot_dir,
+            screenshot_scale,
+            video,
+            video_format,
+            ascii,
+        }) => Palette::find(&palette).and_then(|palette| {
+            let output = HeadlessOutput {
+                path: output,
+                format,
+                palette,
+                screenshots: screenshot.then(|| {
+                    Screenshots::new(Path::new(&screenshot_dir), screenshot_scale as usize)
+                }),
+                video: video.map(|path| (path, video_format)),
+                ascii,
+            };
+
+            // The movie's platform decides the memory size the ROM is
+            // loaded into
+            let movie = match movie {
+                Some(path) => Some((Movie::load(Path::new(&path))?, path)),
+                None => None,
+            };
+            let platform = movie.as_ref().map_or(platform, |(movie, _)| movie.platform);
+
+            let mut machine = Machine::new(hz, false, platform.quirks());
+            setup_headless(&mut machine, &filename, frames, (seed, rng), input, movie).and_then(
+                |(mut script, frames)| {
+                    run_headless(&mut machine, &filename, frames, &mut script, output)
+                },
+            )
+        }),
+        None => run(cli.args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// `chipate disasm`: prints a listing of the ROM.
+fn disasm(
+    file_name: &str,
+    syntax: Syntax,
+    origin: u16,
+    output: Option<&str>,
+) -> Result<(), String> {
+    let rom = fs::read(file_name).map_err(|e| format!("Failed to read {}: {}", file_name, e))?;
+    let listing = disasm::listing(&rom, origin, syntax);
+
+    match output {
+        Some(path) => {
+            fs::write(path, listing).map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        None => {
+            print!("{}", listing);
+            Ok(())
+        }
+    }
+}
+
+/// `chipate asm`: writes the ROM and symbol map. Returns the ROM's file name
+/// and the addresses of `:breakpoint`s.
+fn asm(
+    file_name: &str,
+    output: Option<String>,
+    symbols: Option<String>,
+) -> Result<(String, Vec<u16>), String> {
+    let source = fs::read_to_string(file_name)
+        .map_err(|e| format!("Failed to read {}: {}", file_name, e))?;
+    let program = assembler::assemble(&source).map_err(|e| format!("{}: {}", file_name, e))?;
+
+    if program.rom.len() > XO_MAX_ROM_SIZE {
+        return Err(format!(
+            "ROM is too large, size: {} > {}",
+            program.rom.len(),
+            XO_MAX_ROM_SIZE
+        ));
+    }
+
+    let path = Path::new(file_name);
+    let output = output.unwrap_or_else(|| path.with_extension("ch8").to_string_lossy().into());
+    let symbols = symbols.unwrap_or_else(|| path.with_extension("sym").to_string_lossy().into());
+
+    fs::write(&output, &program.rom).map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    fs::write(&symbols, program.symbol_map())
+        .map_err(|e| format!("Failed to write {}: {}", symbols, e))?;
+
+    println!("Assembled {} ({} bytes)", output, program.rom.len());
+
+    let breakpoints = program.breakpoints.iter().map(|(_, addr)| *addr).collect();
+    Ok((output, breakpoints))
+}
+
+/// Loads the ROM for `chipate headless` and its input: either an input
+/// script, or a movie that also sets the machine up and the run's length.
+fn setup_headless(
+    machine: &mut Machine,
+    file_name: &str,
+    frames: u32,
+    (seed, rng): (Option<u64>, RngMode),
+    input: Option<String>,
+    movie: Option<(Movie, String)>,
+) -> Result<(InputScript, u32), String> {
+    let rom = fs::read(file_name).map_err(|e| format!("Failed to read {}: {}", file_name, e))?;
+    machine
+        .load_rom_bytes(&rom)
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+
+    if let Some((movie, path)) = movie {
+        movie.apply(machine)?;
+        println!("Playing {} ({} frames)", path, movie.frames);
+        return Ok((movie.input, movie.frames));
+    }
+
+    seed_rng(machine, seed, rng);
+    let script = match input {
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?
+            .parse::<InputScript>()
+            .map_err(|e| format!("{}: {}", path, e))?,
+        None => InputScript::default(),
+    };
+    Ok((script, frames))
+}
+
+/// What `chipate headless` writes once the run is over.
+struct HeadlessOutput {
+    path: Option<String>, // Image file for the final frame
+    format: Option<Format>,
+    palette: Palette,
+    screenshots: Option<Screenshots>,
+    video: Option<(String, Option<VideoFormat>)>, // Video of the whole run
+    ascii: bool,
+}
+
+/// `chipate headless`: runs the ROM without initializing SDL, then writes and
+/// hashes the final frame. Exits with status 2 if the ROM faulted.
+fn run_headless(
+    machine: &mut Machine,
+    file_name: &str,
+    frames: u32,
+    script: &mut InputScript,
+    output: HeadlessOutput,
+) -> Result<(), String> {
+    let report = match &output.video {
+        Some((path, format)) => {
+            let mut video = VideoRecorder::create(Path::new(path), *format, &output.palette)?;
+            let report = headless::run_with_sink(machine, frames, script, &mut video);
+            println!("{}", stop_video(video));
+            report
+        }
+        None => headless::run(machine, frames, script),
+    };
+    let pixels = machine.pixels();
 
-    let mut cpu = Cpu::new(args.speed, args.debug);
-    cpu.load_rom(&file_name);
+    if let Some(path) = output.path {
+        let format = output
+            .format
+            .or_else(|| Format::from_path(Path::new(&path)))
+            .ok_or_else(|| format!("Unknown image format for {}; pass --format", path))?;
+        fs::write(&path, format.encode(pixels, &output.palette.table()))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+
+    if let Some(screenshots) = output.screenshots {
+        for path in screenshots.save(&rom_stem(file_name), pixels, &output.palette)? {
+            println!("Saved {}", path.display());
+        }
+    }
+
+    if output.ascii {
+        print!("{}", image::ascii(pixels));
+    }
+
+    println!("Stopped after {} frames: {}", report.frames, report.stop);
+    println!("Framebuffer hash: {:016x}", pixels.hash());
+
+    if report.stop.is_fault() {
+        process::exit(2);
+    }
+    Ok(())
+}
+
+/// Stands in for the SDL frontend in builds without it.
+#[cfg(not(feature = "sdl"))]
+fn run(_args: Args) -> Result<(), String> {
+    Err(format!(
+        "{} was built without the sdl feature; only the disasm, asm and headless commands are available",
+        env!("CARGO_PKG_NAME")
+    ))
+}
+
+/// Runs a ROM in the SDL frontend.
+#[cfg(feature = "sdl")]
+fn run(args: Args) -> Result<(), String> {
+    let mut file_name = args.filename.expect("clap requires a filename");
+
+    // The movie's platform decides the memory size the ROM is loaded into
+    let movie = match &args.play {
+        Some(path) => Some(Movie::load(Path::new(path))?),
+        None => None,
+    };
+    let platform = movie.as_ref().map_or(args.platform, |movie| movie.platform);
+
+    let mut machine = Machine::new(args.hz, args.debug, platform.quirks());
+    let rom =
+        fs::read(&file_name).map_err(|e| format!("Failed to load ROM: {}: {}", file_name, e))?;
+    machine
+        .load_rom_bytes(&rom)
+        .map_err(|e| format!("Failed to load ROM: {}", e))?;
+    println!("Loaded ROM: {} ({} bytes)", file_name, rom.len());
+    machine.debugger.breakpoints.extend(args.breakpoints.iter());
+    machine.fault_policy = args.on_fault;
+
+    // A movie seeds the RNG itself, like in `setup_headless`
+    let mut player = match (&movie, &args.play) {
+        (Some(movie), Some(path)) => {
+            movie.apply(&mut machine)?;
+            println!("Playing {} ({} frames)", path, movie.frames);
+            Some(Player::new(movie))
+        }
+        _ => {
+            seed_rng(&mut machine, args.seed, args.rng);
+            None
+        }
+    };
+    let mut recorder = args
+        .record
+        .as_ref()
+        .map(|_| Recorder::new(&machine, args.platform));
 
     let sdl_context = sdl2::init().expect("Failed to init SDL");
     let timer = sdl_context.timer().expect("SDL context timer failed");
 
     #[cfg(feature = "keymaps")]
     let keymap = if let Some(keymap) = args.keymap {
-        keymaps::parse_keymap_file(Path::new(&keymap))
+        keymaps::parse_keymap_file(Path::new(&keymap)).map_err(|e| e.to_string())?
     } else {
         STANDARD_KEYMAP.to_vec()
     };
@@ -69,51 +702,222 @@ fn main() {
     #[cfg(not(feature = "keymaps"))]
     let keymap = STANDARD_KEYMAP.to_vec();
 
+    let ttf = sdl2::ttf::init().map_err(|e| e.to_string());
+
     let mut keypad = Keypad::new(&sdl_context, keymap);
     let mut sound = Sound::new(&sdl_context);
-    let mut display = display::Display::new(
+    // The hotkey cycles through the presets, starting from the palette
+    // given on the command line
+    let palette = build_palette(&args.palette, [&args.bg, &args.fg, &args.fg2, &args.blend])?;
+    let mut palettes = Palette::presets();
+    let mut palette_index = match palettes.iter().position(|p| *p == palette) {
+        Some(index) => index,
+        None => {
+            palettes.insert(0, palette.clone());
+            0
+        }
+    };
+
+    let window = display::Window::open(
         &sdl_context,
         display::Config {
             fullscreen: args.fullscreen,
             software_render: args.software_render,
-            fg_hex: &args.fg,
-            bg_hex: &args.bg,
+            palette,
         },
     )
-    .unwrap_or_else(|e| {
-        panic!("Failed to create display: {}", e);
-    });
+    .map_err(|e| format!("Failed to create display: {}", e))?;
+    let texture_creator = window.texture_creator();
+    let mut display = display::Display::new(window, &texture_creator)
+        .map_err(|e| format!("Failed to create display: {}", e))?;
+    display.set_filter(args.filter);
+
+    let screenshots = Screenshots::new(
+        Path::new(&args.screenshot_dir),
+        args.screenshot_scale as usize,
+    );
+    let mut video = match &args.record_video {
+        Some(path) => Some(VideoRecorder::create(
+            Path::new(path),
+            args.video_format,
+            display.palette(),
+        )?),
+        None => None,
+    };
+
+    display.font = match &ttf {
+        Ok(ttf) => font::load(ttf, args.font.as_deref()),
+        Err(e) => {
+            println!("Failed to init SDL_ttf: {}", e);
+            None
+        }
+    };
 
     let mut paused = false;
     let mut do_sound = false;
+    let mut browser: Option<RomBrowser> = None;
+    let mut rewinding = false;
+    let mut fast_forward = false;
+    let mut turbo = args.turbo;
+    let mut last_fault: Option<Chip8Error> = None;
+    let mut rewind = Rewind::new(
+        args.rewind,
+        args.rewind_interval,
+        args.rewind_memory.saturating_mul(1024 * 1024),
+    );
+    #[cfg(feature = "savestate")]
+    let mut slot: u8 = 1;
 
     // Frame timing
-    const INTERVAL: u32 = 1_000 / 60;
-    let mut before = timer.ticks();
+    const FRAME_MS: f64 = 1_000.0 / 60.0;
+    const MAX_LAG_MS: f64 = 100.0; // Drop frames rather than catch up on more than this
+    let mut next_frame = timer.ticks() as f64;
     let mut last_second = timer.ticks();
     let mut fps: u16 = 0;
 
     'run: loop {
-        // Input handling
-        match keypad.key_press(&mut cpu.keypad) {
+        // The ROM browser takes over input and the window while it is open
+        if let Some(rom_browser) = &mut browser {
+            let mut close = false;
+
+            match keypad.menu_press() {
+                keypad::Menu::Exit => break 'run,
+                keypad::Menu::None => {}
+                keypad::Menu::Up => rom_browser.up(),
+                keypad::Menu::Down => rom_browser.down(),
+                keypad::Menu::Back => rom_browser.back(),
+                keypad::Menu::Close => close = true,
+                keypad::Menu::Select => {
+                    if let Some(path) = rom_browser.select() {
+                        match fs::read(&path) {
+                            Ok(rom) => match machine.replace_rom(&rom) {
+                                Ok(()) => {
+                                    rewind.clear();
+                                    last_fault = None;
+                                    file_name = path.to_string_lossy().into_owned();
+                                    display.toast(format!(
+                                        "Loaded ROM: {} ({} bytes)",
+                                        rom_name(&file_name),
+                                        rom.len()
+                                    ));
+                                    close = true;
+                                }
+                                Err(e) => display.toast(e.to_string()),
+                            },
+                            Err(e) => display.toast(format!(
+                                "Failed to read ROM: {} ({})",
+                                path.display(),
+                                e
+                            )),
+                        }
+                    }
+                }
+            }
+
+            if close {
+                browser = None;
+                machine.cpu.redraw = true;
+            } else if let Err(e) = rom_browser.render(&mut display) {
+                println!("Failed to draw ROM browser: {}", e);
+            }
+
+            timer.delay(FRAME_MS as u32);
+            next_frame = timer.ticks() as f64;
+            continue;
+        }
+
+        // Input handling. While a movie plays, the keypad belongs to it
+        let mut ignored = [0; 16];
+        let keys = if player.is_some() {
+            &mut ignored
+        } else {
+            machine.keypad_mut()
+        };
+        let mut movie_break = false;
+
+        match keypad.key_press(keys) {
             keypad::State::Exit => break 'run,
             keypad::State::Continue => {}
             keypad::State::Increase => {
-                cpu.speed = cpu.speed.wrapping_add(1);
-                println!("Speed: {} ({} Hz)", cpu.speed, cpu.speed as u32 * 60);
+                movie_break = true;
+                display.toast(format!("Speed: {} Hz", machine.cpu.adjust_hz(1)));
             }
             keypad::State::Decrease => {
-                cpu.speed = cpu.speed.wrapping_sub(1);
-                println!("Speed: {} ({} Hz)", cpu.speed, cpu.speed as u32 * 60);
+                movie_break = true;
+                display.toast(format!("Speed: {} Hz", machine.cpu.adjust_hz(-1)));
+            }
+            keypad::State::FastForward(held) => fast_forward = held,
+            keypad::State::TurboToggle => {
+                turbo = !turbo;
+                display.toast(if turbo { "Turbo on" } else { "Turbo off" });
             }
             keypad::State::Reset => {
-                cpu.reset();
+                movie_break = true;
+                machine.reset();
+                last_fault = None;
+                display.toast("Reset");
             }
             keypad::State::Debug => {
-                cpu.debug = !cpu.debug;
+                if machine.debugger.is_active() {
+                    machine.debugger.resume(&machine.cpu);
+                } else {
+                    machine.debugger.pause();
+                }
+            }
+            keypad::State::Step => machine.debugger.step(&machine.cpu),
+            keypad::State::StepOver => machine.debugger.step_over(&machine.cpu),
+            keypad::State::StepFrame => machine.debugger.step_frame(&machine.cpu),
+            keypad::State::ToggleBreakpoint => {
+                let pc = machine.cpu.pc();
+                let set = machine.debugger.toggle_breakpoint(pc);
+                display.toast(format!(
+                    "Breakpoint {} at {:03X}",
+                    if set { "set" } else { "cleared" },
+                    pc
+                ));
             }
             keypad::State::PauseToggle => {
                 paused = !paused;
+                display.toast(if paused { "Paused" } else { "Running" });
+            }
+            keypad::State::OverlayToggle => {
+                display.overlay = !display.overlay;
+            }
+            keypad::State::NextPalette => {
+                palette_index = (palette_index + 1) % palettes.len();
+                display.set_palette(palettes[palette_index].clone());
+                display.toast(format!("Palette: {}", palettes[palette_index].name));
+            }
+            keypad::State::Screenshot => {
+                let saved =
+                    screenshots.save(&rom_stem(&file_name), machine.pixels(), display.palette());
+                match saved {
+                    Ok(paths) => display.toast(format!("Saved {}", paths[0].display())),
+                    Err(e) => display.toast(e),
+                }
+            }
+            keypad::State::VideoToggle => match video.take() {
+                Some(recorder) => display.toast(stop_video(recorder)),
+                None => {
+                    let format = args.video_format.unwrap_or_default();
+                    let started = screenshots
+                        .path_for(&rom_stem(&file_name), format.extension())
+                        .and_then(|path| {
+                            VideoRecorder::create(&path, Some(format), display.palette())
+                        });
+                    match started {
+                        Ok(recorder) => {
+                            display.toast(format!("Recording {}", recorder.path().display()));
+                            video = Some(recorder);
+                        }
+                        Err(e) => display.toast(e),
+                    }
+                }
+            },
+            keypad::State::NextFilter => {
+                let filter = display.filter().next();
+                display.set_filter(filter);
+                display.toast(format!("Filter: {}", filter));
             }
             keypad::State::Pause => {
                 paused = true;
@@ -121,39 +925,169 @@ fn main() {
             keypad::State::Unpause => {
                 paused = false;
             }
+            #[cfg(feature = "savestate")]
+            keypad::State::SelectSlot(n) => {
+                slot = n;
+                display.toast(format!("Save slot: {}", slot));
+            }
+            #[cfg(feature = "savestate")]
+            keypad::State::SaveState => {
+                let path = state_path(&file_name, slot);
+                match machine.save_state(Path::new(&path)) {
+                    Ok(()) => display.toast(format!("State saved to slot {}", slot)),
+                    Err(e) => display.toast(format!("Failed to save state: {}", e)),
+                }
+            }
+            #[cfg(feature = "savestate")]
+            keypad::State::LoadState => {
+                let path = state_path(&file_name, slot);
+                match machine.load_state(Path::new(&path)) {
+                    Ok(()) => {
+                        movie_break = true;
+                        display.toast(format!("State loaded from slot {}", slot));
+                    }
+                    Err(e) => display.toast(format!("Failed to load state: {}", e)),
+                }
+            }
+            keypad::State::OpenRom => {
+                if display.font.is_some() {
+                    movie_break = true;
+                    let dir = env::current_dir().unwrap_or_else(|_| ".".into());
+                    browser = Some(RomBrowser::new(&dir));
+                    sound.pause();
+                    do_sound = false;
+                    rewinding = false;
+                } else {
+                    display.toast("No font found; pass --font to use the ROM browser");
+                }
+            }
+            keypad::State::Rewind(held) => {
+                rewinding = held && args.rewind > 0;
+                movie_break |= rewinding;
+            }
+            #[cfg(not(feature = "savestate"))]
+            keypad::State::SaveState | keypad::State::LoadState | keypad::State::SelectSlot(_) => {
+                display.toast("Save states are not enabled in this build");
+            }
         }
 
-        if !paused {
-            for _ in 0..cpu.speed {
-                cpu.tick(&mut display);
-            }
+        // The debugger can stop the CPU mid-frame, which a movie can't capture
+        movie_break |= machine.debugger.is_active();
+        if movie_break && (player.is_some() || recorder.is_some()) {
+            display.toast(stop_movie(
+                &mut recorder,
+                &mut player,
+                args.record.as_deref(),
+            ));
         }
 
-        // Frame timing
-        let now = timer.ticks();
-        let dt = now - before;
+        if rewinding {
+            if let Some(state) = rewind.pop() {
+                machine.cpu.restore(&state);
+            }
+        } else if !paused {
+            // Fast-forward runs several frames per displayed frame; turbo runs
+            // as many as fit before the next one is due
+            let speedup = if fast_forward { args.fast_forward } else { 1 };
+            let mut frames = 0;
 
-        if dt < INTERVAL {
-            timer.delay(INTERVAL - dt);
-            continue;
+            loop {
+                if let Some(movie) = &mut player {
+                    if !movie.play(machine.keypad_mut()) {
+                        player = None;
+                        *machine.keypad_mut() = [0; 16];
+                        display.toast("Movie finished");
+                    }
+                }
+
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&machine.cpu.keypad);
+                }
+
+                // Report each fault once, not every frame the ROM keeps hitting it
+                match machine.frame() {
+                    Err(e) if last_fault.as_ref() != Some(&e) => {
+                        display.toast(e.to_string());
+                        last_fault = Some(e);
+                    }
+                    Err(_) => {}
+                    Ok(()) => last_fault = None,
+                }
+                for line in machine.debugger.take_trace() {
+                    println!("{}", line);
+                }
+
+                // Every emulated frame goes into the video, shown or not;
+                // frames the debugger held back aren't emulated time
+                if machine.frame_completed() {
+                    if let Some(recorder) = &mut video {
+                        recorder.present(machine.pixels());
+                    }
+                }
+
+                // Sitting paused in the debugger mustn't flood the history
+                // with copies of the same state
+                if args.rewind > 0 && machine.frame_completed() {
+                    rewind.push(&machine.cpu);
+                }
+
+                frames += 1;
+                let more = if turbo {
+                    (timer.ticks() as f64) < next_frame + FRAME_MS
+                } else {
+                    frames < speedup
+                };
+                if !more || machine.debugger.is_active() {
+                    break;
+                }
+            }
         }
 
-        before = now;
+        let now = timer.ticks();
         fps += 1;
 
-        if now - last_second > 1000 {
-            println!(
-                "FPS: {} | {}Hz ({}) | {}",
+        if now - last_second >= 1000 {
+            display.info = format!(
+                "FPS: {} | {} Hz | {} | {}",
                 fps,
-                (cpu.speed as u32 * 60),
-                cpu.speed,
-                if paused { "Paused" } else { "Running" },
+                machine.cpu.hz,
+                if paused {
+                    "Paused"
+                } else if turbo {
+                    "Turbo"
+                } else if fast_forward {
+                    "Fast-forward"
+                } else if machine.cpu.waiting_for_key() {
+                    "Waiting for key"
+                } else {
+                    "Running"
+                },
+                rom_name(&file_name),
             );
+            if args.rewind > 0 {
+                display.info += &format!(
+                    " | Rewind: {:.1} s ({:.1} MiB)",
+                    rewind.seconds(),
+                    rewind.memory_usage() as f64 / (1024.0 * 1024.0)
+                );
+            }
             last_second = now;
             fps = 0;
         }
 
-        let do_sound_now = cpu.sound_timer > 0;
+        display.panel = if machine.debugger.is_active() {
+            machine.debugger.view(&machine.cpu)
+        } else {
+            Vec::new()
+        };
+
+        if !machine.present(&mut display) && (display.overlay_active() || display.animating()) {
+            display.refresh();
+        }
+
+        let do_sound_now = machine.sound_active();
+
+        sound.set_pattern(machine.cpu.audio_pattern, machine.cpu.pitch);
 
         if do_sound != do_sound_now {
             if do_sound_now {
@@ -165,6 +1099,62 @@ fn main() {
             do_sound = do_sound_now;
         }
 
-        cpu.update_timers(dt as f32);
+        // Frame timing: frames are due every 1/60 s on a fixed schedule, so
+        // host jitter doesn't change the emulation speed. Late frames run
+        // back to back until emulation has caught up.
+        next_frame += FRAME_MS;
+        let now = timer.ticks() as f64;
+        if next_frame > now {
+            timer.delay((next_frame - now) as u32);
+        } else if now - next_frame > MAX_LAG_MS {
+            next_frame = now;
+        }
+    }
+
+    if recorder.is_some() {
+        println!(
+            "{}",
+            stop_movie(&mut recorder, &mut player, args.record.as_deref())
+        );
+    }
+
+    if let Some(recorder) = video {
+        println!("{}", stop_video(recorder));
+    }
+
+    Ok(())
+}
+
+/// Ends movie recording or playback, e.g. after a reset or speed change
+/// that the movie can't capture. A recording is saved as it stands.
+/// Returns a message for the user.
+#[cfg(feature = "sdl")]
+fn stop_movie(
+    recorder: &mut Option<Recorder>,
+    player: &mut Option<Player>,
+    path: Option<&str>,
+) -> String {
+    if player.take().is_some() {
+        return "Movie playback stopped".to_string();
+    }
+
+    match (recorder.take(), path) {
+        (Some(recorder), Some(path)) => {
+            let frames = recorder.frames();
+            match recorder.finish().save(Path::new(path)) {
+                Ok(()) => format!("Movie saved to {} ({} frames)", path, frames),
+                Err(e) => format!("Failed to save movie: {}", e),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Finishes a video recording and describes the result.
+fn stop_video(recorder: VideoRecorder) -> String {
+    let path = recorder.path().display().to_string();
+    match recorder.finish() {
+        Ok(frames) => format!("Video saved to {} ({} frames)", path, frames),
+        Err(e) => format!("Failed to save video: {}", e),
     }
 }
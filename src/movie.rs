@@ -0,0 +1,227 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use crate::{
+    chip8::Machine,
+    headless::{InputScript, KeyEvent},
+    quirks::Platform,
+    rng::RngMode,
+};
+
+/// First line of every movie file.
+const HEADER: &str = "chipate-movie 1";
+
+/**
+ * A recorded session, replayable from power-on.
+ *
+ * Movies are text files: a header with the ROM hash and the machine
+ * configuration, then every keypad change in the input script format. The
+ * same file can be played back in the window or by `chipate headless`.
+ *
+ * ```text
+ * chipate-movie 1
+ * rom 5d1c5e2a9b0f7c43
+ * platform vip
+ * hz 480
+ * seed 1234
+ * rng splitmix
+ * frames 900
+ * 30 5 down
+ * 32 5 up
+ * ```
+ */
+#[derive(Clone, Debug)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub hz: u32,
+    pub seed: u64,
+    pub rng: RngMode,
+    pub frames: u32, // Length of the recording
+    pub input: InputScript,
+}
+
+impl Movie {
+    /// An empty movie for the machine's ROM and current configuration.
+    pub fn new(machine: &Machine, platform: Platform) -> Movie {
+        Movie {
+            rom_hash: machine.cpu.rom_hash(),
+            platform,
+            hz: machine.cpu.hz,
+            seed: machine.cpu.rng().seed(),
+            rng: machine.cpu.rng().mode(),
+            frames: 0,
+            input: InputScript::default(),
+        }
+    }
+
+    /// Configures a freshly loaded machine the way the movie was recorded.
+    /// Fails if the machine is running a different ROM.
+    pub fn apply(&self, machine: &mut Machine) -> Result<(), String> {
+        if machine.cpu.rom_hash() != self.rom_hash {
+            return Err("Movie was recorded with a different ROM".to_string());
+        }
+
+        machine.cpu.set_quirks(self.platform.quirks());
+        machine.cpu.hz = self.hz;
+        machine.cpu.seed_rng(self.seed, self.rng);
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "platform {}", self.platform)?;
+        writeln!(f, "hz {}", self.hz)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "rng {}", self.rng)?;
+        writeln!(f, "frames {}", self.frames)?;
+        write!(f, "{}", self.input)
+    }
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Movie, String> {
+        let mut lines = s.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err("not a chipate movie".to_string());
+        }
+
+        let (mut rom_hash, mut platform, mut hz) = (None, None, None);
+        let (mut seed, mut rng, mut frames) = (None, None, None);
+
+        // Header fields are blanked out so the input script keeps its line numbers
+        let mut events = String::from("\n");
+        for (i, line) in lines.enumerate() {
+            let fields: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let (name, value) = match fields[..] {
+                [name, value] if name.parse::<u32>().is_err() => (name, value),
+                _ => {
+                    events.push_str(line);
+                    events.push('\n');
+                    continue;
+                }
+            };
+
+            let line = i + 2;
+            let invalid = |e: String| format!("line {}: invalid {}: {}", line, name, e);
+            match name {
+                "rom" => {
+                    rom_hash =
+                        Some(u64::from_str_radix(value, 16).map_err(|e| invalid(e.to_string()))?)
+                }
+                "platform" => platform = Some(value.parse::<Platform>().map_err(invalid)?),
+                "hz" => hz = Some(value.parse::<u32>().map_err(|e| invalid(e.to_string()))?),
+                "seed" => seed = Some(value.parse::<u64>().map_err(|e| invalid(e.to_string()))?),
+                "rng" => rng = Some(value.parse::<RngMode>().map_err(invalid)?),
+                "frames" => {
+                    frames = Some(value.parse::<u32>().map_err(|e| invalid(e.to_string()))?)
+                }
+                _ => return Err(format!("line {}: unknown field {}", line, name)),
+            }
+            events.push('\n');
+        }
+
+        let missing = |name: &str| format!("missing {}", name);
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            platform: platform.ok_or_else(|| missing("platform"))?,
+            hz: hz.ok_or_else(|| missing("hz"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            rng: rng.ok_or_else(|| missing("rng"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            input: events.parse()?,
+        })
+    }
+}
+
+/// Records keypad changes into a `Movie`, one call per emulated frame.
+pub struct Recorder {
+    movie: Movie,
+    keypad: [u8; 16], // Key state as of the last recorded frame
+}
+
+impl Recorder {
+    /// Starts recording. The machine should have just been loaded, so that
+    /// playback starts from the same state.
+    pub fn new(machine: &Machine, platform: Platform) -> Recorder {
+        Recorder {
+            movie: Movie::new(machine, platform),
+            keypad: [0; 16],
+        }
+    }
+
+    /// Notes the key state the next frame runs with. Call right before
+    /// `Machine::frame`.
+    pub fn record(&mut self, keypad: &[u8; 16]) {
+        for (key, (&now, &before)) in keypad.iter().zip(&self.keypad).enumerate() {
+            if (now != 0) != (before != 0) {
+                self.movie.input.push(KeyEvent {
+                    frame: self.movie.frames,
+                    key: key as u8,
+                    down: now != 0,
+                });
+            }
+        }
+
+        self.keypad = *keypad;
+        self.movie.frames += 1;
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.movie.frames
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a `Movie`'s input back into the keypad, one call per emulated frame.
+pub struct Player {
+    input: InputScript,
+    frame: u32,
+    frames: u32,
+}
+
+impl Player {
+    pub fn new(movie: &Movie) -> Player {
+        Player {
+            input: movie.input.clone(),
+            frame: 0,
+            frames: movie.frames,
+        }
+    }
+
+    /// Sets the key state for the next frame. Call right before
+    /// `Machine::frame`; returns false once the movie has ended.
+    pub fn play(&mut self, keypad: &mut [u8; 16]) -> bool {
+        if self.frame >= self.frames {
+            return false;
+        }
+
+        self.input.apply(self.frame, keypad);
+        self.frame += 1;
+        true
+    }
+}
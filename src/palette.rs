@@ -0,0 +1,193 @@
+use std::{fs, path::Path};
+
+use crate::error::Chip8Error;
+
+/// Most colors a palette can have: one per combination of four bitplanes.
+pub const MAX_COLORS: usize = 16;
+
+/// Built-in palettes: name and colors for background, plane 1, plane 2 and
+/// both planes.
+const PRESETS: [(&str, [&str; 4]); 7] = [
+    ("gameboy", ["#0f380f", "#8bac0f", "#306230", "#9bbc0f"]),
+    ("amber", ["#140c00", "#ffb000", "#a05c00", "#ffd57a"]),
+    ("mono", ["#000000", "#ffffff", "#aaaaaa", "#555555"]),
+    ("vip", ["#101418", "#d8e4f0", "#6c7888", "#a8b8c8"]),
+    ("octo", ["#996600", "#ffcc00", "#ff6600", "#662200"]),
+    ("contrast", ["#000000", "#ffffff", "#ffff00", "#00ffff"]),
+    ("colorblind", ["#000000", "#e69f00", "#56b4e9", "#f0e442"]),
+];
+
+/// Parses `#RRGGBB` or `#RGB`; the `#` is optional.
+pub fn parse_color(hex: &str) -> Result<[u8; 3], Chip8Error> {
+    let invalid = || Chip8Error::InvalidColor(hex.to_string());
+
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| invalid());
+    match digits.len() {
+        6 => Ok([
+            channel(&digits[0..2])?,
+            channel(&digits[2..4])?,
+            channel(&digits[4..6])?,
+        ]),
+        // #abc is short for #aabbcc
+        3 => Ok([
+            channel(&digits[0..1])? * 0x11,
+            channel(&digits[1..2])? * 0x11,
+            channel(&digits[2..3])? * 0x11,
+        ]),
+        _ => Err(invalid()),
+    }
+}
+
+/**
+ * Colors for framebuffer pixels, indexed by pixel value.
+ *
+ * Entry 0 is the background, 1 is plane 1, 2 is plane 2 and 3 is both; up
+ * to 16 entries cover every combination of four planes. Pixels with no
+ * entry of their own are drawn in the plane 1 color.
+ *
+ * Palette files list one color per line in index order. Anything after the
+ * color is ignored, blank lines and lines starting with `;` are skipped:
+ *
+ * ```text
+ * ; Dusk
+ * #1b1b3a  background
+ * #f4d35e  plane 1
+ * #ee964b  plane 2
+ * #f95738  both
+ * ```
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    colors: Vec<[u8; 3]>, // 2 to MAX_COLORS entries
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::preset("gameboy").unwrap()
+    }
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<[u8; 3]>) -> Result<Palette, String> {
+        if !(2..=MAX_COLORS).contains(&colors.len()) {
+            return Err(format!(
+                "A palette needs 2 to {} colors, got {}",
+                MAX_COLORS,
+                colors.len()
+            ));
+        }
+
+        Ok(Palette {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    /// A built-in palette by name.
+    pub fn preset(name: &str) -> Option<Palette> {
+        let (name, hexes) = PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))?;
+        let colors = hexes.iter().map(|hex| parse_color(hex).unwrap()).collect();
+
+        Some(Palette {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    /// All built-in palettes, in the order the hotkey cycles through them.
+    pub fn presets() -> Vec<Palette> {
+        PRESETS
+            .iter()
+            .filter_map(|(name, _)| Palette::preset(name))
+            .collect()
+    }
+
+    /// A built-in palette, or failing that a palette file.
+    pub fn find(name_or_path: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::preset(name_or_path) {
+            return Ok(palette);
+        }
+
+        let path = Path::new(name_or_path);
+        if !path.exists() {
+            let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "Unknown palette: {} (expected a file or one of {})",
+                name_or_path,
+                names.join(", ")
+            ));
+        }
+
+        Palette::load(path).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, Chip8Error> {
+        let error = |message: String| Chip8Error::Palette {
+            path: path.display().to_string(),
+            message,
+        };
+
+        let contents = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Palette::parse(&name, &contents).map_err(error)
+    }
+
+    /// Parses the contents of a palette file.
+    pub fn parse(name: &str, contents: &str) -> Result<Palette, String> {
+        let mut colors = Vec::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let hex = line.split_whitespace().next().unwrap_or_default();
+            let color = parse_color(hex).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            colors.push(color);
+        }
+
+        Palette::new(name, colors)
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// The color for a pixel value.
+    pub fn color(&self, pixel: u8) -> [u8; 3] {
+        match self.colors.get(pixel as usize) {
+            Some(&color) => color,
+            None if pixel == 0 => self.colors[0],
+            None => self.colors[1],
+        }
+    }
+
+    /// Replaces the color at `index`, e.g. from `--fg`. The palette grows
+    /// if it had no such entry.
+    pub fn set(&mut self, index: usize, color: [u8; 3]) {
+        assert!(index < MAX_COLORS, "palette index {} out of range", index);
+
+        while self.colors.len() <= index {
+            self.colors.push(self.colors[1]);
+        }
+        self.colors[index] = color;
+    }
+
+    /// Colors for every possible pixel value, for code that looks pixels
+    /// up directly (`image`, `filter`).
+    pub fn table(&self) -> [[u8; 3]; MAX_COLORS] {
+        std::array::from_fn(|pixel| self.color(pixel as u8))
+    }
+}
@@ -0,0 +1,143 @@
+use std::{fmt, str::FromStr};
+
+use crate::consts::{MAX_ROM_SIZE, MEMORY_SIZE, XO_MAX_ROM_SIZE, XO_MEMORY_SIZE};
+
+/**
+ * Behaviour toggles for the opcodes that differ between CHIP-8 interpreters.
+ *
+ * Use one of the presets (`Quirks::vip`, `Quirks::schip`, `Quirks::xochip`)
+ * or `Platform::quirks` rather than building one by hand.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift Vy into Vx instead of shifting Vx in place.
+    pub shift_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored/loaded.
+    pub load_store_increment: bool,
+    /// BNNN is treated as BXNN and jumps to XNN + Vx instead of NNN + V0.
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip: bool,
+    /// DXYN waits for the next frame before drawing, limiting draws to one per frame.
+    pub display_wait: bool,
+    /// FX1E sets VF to 1 when I goes past 0xFFF and to 0 otherwise, as some
+    /// later interpreters did. None of the presets enable it.
+    pub index_overflow: bool,
+    /// 64 KiB of memory instead of 4 KiB, for XO-CHIP's `i := long`.
+    pub extended_memory: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const fn vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_increment: true,
+            jump_vx: false,
+            vf_reset: true,
+            clip: true,
+            display_wait: true,
+            index_overflow: false,
+            extended_memory: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP 48.
+    pub const fn schip() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_increment: false,
+            jump_vx: true,
+            vf_reset: false,
+            clip: true,
+            display_wait: false,
+            index_overflow: false,
+            extended_memory: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub const fn xochip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_increment: true,
+            jump_vx: false,
+            vf_reset: false,
+            clip: false,
+            display_wait: false,
+            index_overflow: false,
+            extended_memory: true,
+        }
+    }
+
+    /// Bytes of memory the CPU has.
+    pub const fn memory_size(&self) -> usize {
+        if self.extended_memory {
+            XO_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        }
+    }
+
+    /// Largest ROM that fits in memory after 0x200.
+    pub const fn max_rom_size(&self) -> usize {
+        if self.extended_memory {
+            XO_MAX_ROM_SIZE
+        } else {
+            MAX_ROM_SIZE
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::vip()
+    }
+}
+
+/// A named platform, selecting a quirk preset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Vip,
+    Schip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Vip => Quirks::vip(),
+            Platform::Schip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Ok(Platform::Vip),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::Schip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform: {} (expected vip, schip or xochip)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Vip => "vip",
+            Platform::Schip => "schip",
+            Platform::XoChip => "xochip",
+        })
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use crate::{cpu::Cpu, state::CpuState};
+
+/// A run of memory bytes starting at `offset`.
+type Run = (usize, Vec<u8>);
+
+/// An older snapshot, stored as the difference from the snapshot after it.
+struct Entry {
+    state: CpuState,  // Everything but memory
+    memory: Vec<Run>, // Bytes to write over the newer snapshot's memory
+    size: usize,
+}
+
+/**
+ * Ring buffer of recent CPU snapshots for rewinding.
+ *
+ * Only the newest snapshot is kept in full. Every older one is stored as the
+ * memory bytes that differ from the snapshot taken after it, which keeps the
+ * typical frame down to registers, framebuffer and a handful of bytes. The
+ * buffer is bounded both by a number of snapshots and by a byte budget; the
+ * oldest snapshots are dropped first.
+ */
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    max_bytes: usize,
+    frame: u32,
+    latest: Option<CpuState>,
+    entries: VecDeque<Entry>,
+    bytes: usize,
+}
+
+impl Rewind {
+    /// Keeps up to `seconds` of history, taking a snapshot every `interval`
+    /// frames and using at most `max_bytes` for older snapshots.
+    pub fn new(seconds: u32, interval: u32, max_bytes: usize) -> Rewind {
+        let interval = interval.max(1);
+
+        Rewind {
+            interval,
+            capacity: (seconds as usize).saturating_mul(60) / interval as usize,
+            max_bytes,
+            frame: 0,
+            latest: None,
+            entries: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Called once per emulated frame; snapshots the CPU every `interval` frames.
+    pub fn push(&mut self, cpu: &Cpu) {
+        self.frame += 1;
+
+        if self.frame < self.interval {
+            return;
+        }
+        self.frame = 0;
+
+        let newer = cpu.snapshot();
+
+        if let Some(mut older) = self.latest.take() {
+            let memory = diff(&newer.memory, &older.memory);
+            older.memory = Vec::new();
+
+            let size = std::mem::size_of::<Entry>()
+                + older.pixels.width() * older.pixels.height()
+                + memory
+                    .iter()
+                    .map(|(_, run)| std::mem::size_of::<Run>() + run.len())
+                    .sum::<usize>();
+
+            self.entries.push_back(Entry {
+                state: older,
+                memory,
+                size,
+            });
+            self.bytes += size;
+
+            while self.entries.len() > self.capacity || self.bytes > self.max_bytes {
+                match self.entries.pop_front() {
+                    Some(entry) => self.bytes -= entry.size,
+                    None => break,
+                }
+            }
+        }
+
+        self.latest = Some(newer);
+    }
+
+    /// Steps back one snapshot and returns it, or None if the buffer is exhausted.
+    pub fn pop(&mut self) -> Option<CpuState> {
+        let entry = self.entries.pop_back()?;
+        let newer = self.latest.take()?;
+        self.bytes -= entry.size;
+
+        let mut memory = newer.memory;
+        for (offset, run) in entry.memory {
+            memory[offset..offset + run.len()].copy_from_slice(&run);
+        }
+
+        let older = CpuState {
+            memory,
+            ..entry.state
+        };
+
+        self.latest = Some(older.clone());
+        self.frame = 0;
+        Some(older)
+    }
+
+    /// Seconds of history currently available.
+    pub fn seconds(&self) -> f32 {
+        self.entries.len() as f32 * self.interval as f32 / 60.0
+    }
+
+    /// Approximate memory used by the older snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.entries.clear();
+        self.bytes = 0;
+        self.frame = 0;
+    }
+}
+
+/// Runs of bytes in `to` that differ from `from`.
+fn diff(from: &[u8], to: &[u8]) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut i = 0;
+
+    while i < to.len() {
+        if from[i] == to[i] {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < to.len() && from[i] != to[i] {
+            i += 1;
+        }
+        runs.push((start, to[start..i].to_vec()));
+    }
+
+    runs
+}
@@ -0,0 +1,107 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "savestate")]
+use serde::{Deserialize, Serialize};
+
+/// Which algorithm CXNN draws its random bytes from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "savestate", derive(Serialize, Deserialize))]
+pub enum RngMode {
+    #[default]
+    SplitMix, // SplitMix64, good quality and fully determined by the seed
+    Vip, // Modelled on the COSMAC VIP interpreter's byte-reading routine
+}
+
+impl FromStr for RngMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RngMode, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "splitmix" | "default" => Ok(RngMode::SplitMix),
+            "vip" => Ok(RngMode::Vip),
+            _ => Err(format!("Unknown RNG: {} (expected splitmix or vip)", s)),
+        }
+    }
+}
+
+impl fmt::Display for RngMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RngMode::SplitMix => "splitmix",
+            RngMode::Vip => "vip",
+        })
+    }
+}
+
+/**
+ * Seedable random number generator for CXNN.
+ *
+ * The generator is part of the CPU state, so save states, rewind and replays
+ * continue the exact same sequence. Two machines seeded alike and fed the
+ * same input behave identically.
+ *
+ * The VIP mode follows the shape of the original interpreter's routine: each
+ * call advances a pointer through the first page of memory, adds the byte it
+ * finds there to the previous result and returns the sum. On the VIP that
+ * page holds the interpreter itself; here it holds the font.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "savestate", derive(Serialize, Deserialize))]
+pub struct Rng {
+    mode: RngMode,
+    seed: u64,
+    state: u64,  // SplitMix64 state
+    pointer: u8, // VIP: offset of the next byte to read
+    last: u8,    // VIP: previous result
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new(0, RngMode::default())
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64, mode: RngMode) -> Rng {
+        Rng {
+            mode,
+            seed,
+            state: seed,
+            pointer: seed as u8,
+            last: (seed >> 8) as u8,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn mode(&self) -> RngMode {
+        self.mode
+    }
+
+    /// Starts the sequence over from the seed.
+    pub fn reset(&mut self) {
+        *self = Rng::new(self.seed, self.mode);
+    }
+
+    /// Next random byte. `memory` is only read in VIP mode.
+    pub fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self.mode {
+            RngMode::SplitMix => (self.next_u64() >> 56) as u8,
+            RngMode::Vip => {
+                self.pointer = self.pointer.wrapping_add(1);
+                self.last = self.last.wrapping_add(memory[self.pointer as usize]);
+                self.last
+            }
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{framebuffer::Framebuffer, image, palette::Palette};
+
+/**
+ * Writes the framebuffer to timestamped PNG files.
+ *
+ * Screenshots are taken from the emulated framebuffer, not the window, so
+ * they are pixel exact at the native 64x32 or 128x64 resolution and use the
+ * palette's colors. With a `scale` above 1 an enlarged copy is written next
+ * to each one, for pasting where tiny images get blurred.
+ */
+#[derive(Clone, Debug)]
+pub struct Screenshots {
+    pub dir: PathBuf,
+    pub scale: usize, // Also write a copy this many times larger, unless 1
+}
+
+impl Screenshots {
+    pub fn new(dir: &Path, scale: usize) -> Screenshots {
+        Screenshots {
+            dir: dir.to_path_buf(),
+            scale: scale.max(1),
+        }
+    }
+
+    /// A new `<name>-<timestamp>.<extension>` path in `dir`, e.g. for a
+    /// video. Creates `dir` if needed.
+    pub fn path_for(&self, name: &str, extension: &str) -> Result<PathBuf, String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        // Several files in the same second get numbered
+        let stem = format!("{}-{}", name, timestamp(SystemTime::now()));
+        let mut path = self.dir.join(format!("{}.{}", stem, extension));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = self.dir.join(format!("{}-{}.{}", stem, n, extension));
+        }
+        Ok(path)
+    }
+
+    /// Saves `pixels` as `<name>-<timestamp>.png` in `dir`, and the scaled
+    /// copy as `<name>-<timestamp>-<scale>x.png`. Returns the files written.
+    pub fn save(
+        &self,
+        name: &str,
+        pixels: &Framebuffer,
+        palette: &Palette,
+    ) -> Result<Vec<PathBuf>, String> {
+        let path = self.path_for(name, "png")?;
+        let base = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let palette = palette.table();
+        let mut images = vec![(format!("{}.png", base), image::png(pixels, &palette))];
+        if self.scale > 1 {
+            let scaled = image::scale(pixels, self.scale);
+            images.push((
+                format!("{}-{}x.png", base, self.scale),
+                image::png(&scaled, &palette),
+            ));
+        }
+
+        let mut paths = Vec::new();
+        for (file_name, png) in images {
+            let path = self.dir.join(file_name);
+            fs::write(&path, png)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
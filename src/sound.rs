@@ -1,15 +1,39 @@
 use sdl2::audio::{AudioCallback, AudioSpecDesired};
 
-pub struct SquareWave {
+use crate::consts::*;
+
+pub struct Tone {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    freq: f32,                 // Output sample rate
+    pattern: Option<[u8; 16]>, // XO-CHIP 1-bit audio pattern, or None for a square wave
+    pattern_inc: f32,          // Pattern bits per output sample
+    pattern_pos: f32,          // Current bit in the pattern (0..128)
+}
+
+/// XO-CHIP audio pattern playback rate in bits per second for a given pitch.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for Tone {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
+        if let Some(pattern) = self.pattern {
+            // Play back the pattern buffer, one bit at a time, looping
+            for x in out.iter_mut() {
+                let bit = self.pattern_pos as usize;
+                let set = pattern[bit / 8] >> (7 - bit % 8) & 1 != 0;
+
+                *x = if set { self.volume } else { -self.volume };
+                self.pattern_pos = (self.pattern_pos + self.pattern_inc) % 128.0;
+            }
+
+            return;
+        }
+
         // Generate a square wave
         for x in out.iter_mut() {
             *x = if self.phase <= 0.5 {
@@ -23,7 +47,9 @@ impl AudioCallback for SquareWave {
 }
 
 pub struct Sound {
-    device: sdl2::audio::AudioDevice<SquareWave>,
+    device: sdl2::audio::AudioDevice<Tone>,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
 }
 
 impl Sound {
@@ -39,15 +65,38 @@ impl Sound {
         let device = audio_subsystem
             .open_playback(None, &desired_spec, move |spec| {
                 // initialize the audio callback
-                SquareWave {
+                Tone {
                     phase_inc: 440.0 / spec.freq as f32,
                     phase: 0.0,
                     volume: 0.25,
+                    freq: spec.freq as f32,
+                    pattern: None,
+                    pattern_inc: pattern_rate(DEFAULT_PITCH) / spec.freq as f32,
+                    pattern_pos: 0.0,
                 }
             })
             .unwrap();
 
-        Sound { device }
+        Sound {
+            device,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    /// Updates the XO-CHIP audio pattern and pitch. Passing `None` plays the
+    /// default 440 Hz square wave.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, pitch: u8) {
+        if self.pattern == pattern && self.pitch == pitch {
+            return;
+        }
+
+        self.pattern = pattern;
+        self.pitch = pitch;
+
+        let mut tone = self.device.lock();
+        tone.pattern = pattern;
+        tone.pattern_inc = pattern_rate(pitch) / tone.freq;
     }
 
     pub fn resume(&mut self) {
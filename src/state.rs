@@ -0,0 +1,155 @@
+use crate::{
+    consts::{MEMORY_SIZE, XO_MEMORY_SIZE},
+    cpu::KeyWait,
+    framebuffer::Framebuffer,
+    rng::Rng,
+};
+
+#[cfg(feature = "savestate")]
+use crate::cpu::Cpu;
+#[cfg(feature = "savestate")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "savestate")]
+use std::{fs, path::Path};
+
+/**
+ * Complete snapshot of a `Cpu`'s machine state.
+ *
+ * Configuration (speed, debug mode, quirks) is not part of the state; a
+ * snapshot is restored into a `Cpu` that is already set up for the game.
+ */
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "savestate", derive(Serialize, Deserialize))]
+pub struct CpuState {
+    pub memory: Vec<u8>,
+    pub vreg: [u8; 16],
+    pub ireg: u16,
+    pub pc: u16,
+    pub stack: [u16; 16],
+    pub sp: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub cycles: u32,
+    pub rpl: [u8; 16],
+    pub halted: bool,
+    pub planes: u8,
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub rng: Rng,
+    pub key_wait: KeyWait,
+    pub drawn: bool,
+    pub pixels: Framebuffer,
+    pub keypad: [u8; 16],
+}
+
+impl CpuState {
+    /// Checks the invariants `Cpu::restore` and the CPU rely on, so that a
+    /// damaged snapshot is rejected instead of crashing the emulator later.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.memory.len() != MEMORY_SIZE && self.memory.len() != XO_MEMORY_SIZE {
+            return Err(format!(
+                "memory is {} bytes (expected {} or {})",
+                self.memory.len(),
+                MEMORY_SIZE,
+                XO_MEMORY_SIZE
+            ));
+        }
+        if self.sp as usize > self.stack.len() {
+            return Err(format!("stack pointer {} is out of range", self.sp));
+        }
+        if self.planes > 0b11 {
+            return Err(format!("invalid bitplanes {}", self.planes));
+        }
+        if let KeyWait::Release(key) = self.key_wait {
+            if key > 0xF {
+                return Err(format!("waiting for invalid key {}", key));
+            }
+        }
+        if !self.pixels.is_valid() {
+            return Err("framebuffer is malformed".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a hash, used to tie save states to the ROM they were made
+/// with and to fingerprint framebuffers.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/**
+ * Save state files
+ *
+ * A save state file is the magic bytes `C8ST`, a little-endian u32 format
+ * version, then the bincode-encoded `SaveFile`.
+ */
+#[cfg(feature = "savestate")]
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Bumped whenever `CpuState` changes shape; older files are rejected.
+#[cfg(feature = "savestate")]
+pub const STATE_VERSION: u32 = 4;
+
+#[cfg(feature = "savestate")]
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    rom_hash: u64,
+    state: CpuState,
+}
+
+/// Writes the CPU's state to `path`.
+#[cfg(feature = "savestate")]
+pub fn save(cpu: &Cpu, path: &Path) -> Result<(), String> {
+    let file = SaveFile {
+        rom_hash: cpu.rom_hash(),
+        state: cpu.snapshot(),
+    };
+
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&STATE_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut buf, &file)
+        .map_err(|e| format!("Failed to encode state: {}", e))?;
+
+    fs::write(path, buf).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Restores the CPU's state from `path`. Fails without touching the CPU if
+/// the file is from another format version, was made with another ROM or
+/// is damaged.
+#[cfg(feature = "savestate")]
+pub fn load(cpu: &mut Cpu, path: &Path) -> Result<(), String> {
+    let buf = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    if buf.len() < 8 || &buf[..4] != MAGIC {
+        return Err(format!("{} is not a save state", path.display()));
+    }
+
+    let version = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    if version != STATE_VERSION {
+        return Err(format!(
+            "Unsupported save state version: {} (expected {})",
+            version, STATE_VERSION
+        ));
+    }
+
+    let file: SaveFile =
+        bincode::deserialize(&buf[8..]).map_err(|e| format!("Failed to decode state: {}", e))?;
+
+    if file.rom_hash != cpu.rom_hash() {
+        return Err("Save state was made with a different ROM".to_string());
+    }
+
+    file.state
+        .validate()
+        .map_err(|e| format!("{} is corrupted: {}", path.display(), e))?;
+
+    if file.state.memory.len() != cpu.memory_size() {
+        return Err("Save state was made for a platform with a different memory size".to_string());
+    }
+
+    cpu.restore(&file.state);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use crate::framebuffer::Framebuffer;
+
+/**
+ * Receives finished frames from the emulator.
+ *
+ * The CPU never draws directly; it only marks its framebuffer dirty. The
+ * frontend hands the framebuffer to a sink once per 60 Hz frame through
+ * `Machine::present`.
+ */
+pub trait VideoSink {
+    fn present(&mut self, pixels: &Framebuffer);
+}
+
+/// Discards every frame. Useful for running the core without any output.
+#[derive(Default)]
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn present(&mut self, _pixels: &Framebuffer) {}
+}
+
+/// Keeps a copy of every frame it is given, oldest first.
+#[derive(Default)]
+pub struct RecordingSink {
+    pub frames: Vec<Framebuffer>,
+}
+
+impl RecordingSink {
+    pub fn new() -> RecordingSink {
+        RecordingSink::default()
+    }
+
+    pub fn last(&self) -> Option<&Framebuffer> {
+        self.frames.last()
+    }
+}
+
+impl VideoSink for RecordingSink {
+    fn present(&mut self, pixels: &Framebuffer) {
+        self.frames.push(pixels.clone());
+    }
+}
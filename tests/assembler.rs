@@ -0,0 +1,166 @@
+//! Octo assembler: small snippets checked against their exact bytes, and
+//! the errors for programs that can't be assembled.
+
+use chipate::assembler;
+
+/// Assembles `source`, panicking with the error if it fails.
+fn rom(source: &str) -> Vec<u8> {
+    assembler::assemble(source)
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source))
+        .rom
+}
+
+/// Assembles `source` and returns the error.
+fn error(source: &str) -> String {
+    match assembler::assemble(source) {
+        Ok(program) => panic!("assembled to {:02X?}\n{}", program.rom, source),
+        Err(e) => e,
+    }
+}
+
+#[test]
+fn labels_past_0xfff_are_out_of_range() {
+    // Backward reference
+    assert_eq!(
+        error(":org 0x1000\n: far\n:org 0x300\njump far"),
+        "line 4: label far at 0x1000 is out of range"
+    );
+
+    // Forward reference, patched at the end
+    assert_eq!(
+        error(":call far\n:org 0x1000\n: far\nreturn"),
+        "line 1: label far at 0x1000 is out of range"
+    );
+    assert_eq!(
+        error("i := far\njump0 far\n:org 0x1000\n: far"),
+        "line 1: label far at 0x1000 is out of range"
+    );
+    assert_eq!(
+        error(":unpack 0xA far\n:org 0x1000\n: far"),
+        "line 1: label far at 0x1000 is out of range"
+    );
+
+    // `i := long` takes any 16-bit address
+    let far = rom("i := long far\n:org 0x1000\n: far\n0xAB");
+    assert_eq!(&far[..4], &[0xF0, 0x00, 0x10, 0x00]);
+}
+
+#[test]
+fn blocks_ending_past_0xfff_are_out_of_range() {
+    assert_eq!(
+        error(":org 0xFFA\nif v0 == 1 begin\nv1 := 2\nend"),
+        "line 4: end at 0x1000 is out of range"
+    );
+    assert_eq!(
+        error(":org 0xFF8\nloop\nwhile v0 != 1\nv0 += 1\nagain"),
+        "line 5: again at 0x1000 is out of range"
+    );
+}
+
+#[test]
+fn constants() {
+    assert_eq!(
+        rom(":const SPEED 5\nv0 := SPEED\nv1 += SPEED"),
+        [0x60, 0x05, 0x71, 0x05]
+    );
+}
+
+#[test]
+fn calc() {
+    assert_eq!(
+        rom(":calc WIDTH { 8 * 2 }\n:calc HALF { WIDTH / 2 }\nv0 := WIDTH\nv1 := HALF"),
+        [0x60, 0x10, 0x61, 0x08]
+    );
+
+    // No precedence, evaluated right to left like Octo
+    assert_eq!(rom(":calc X { 2 * 3 + 1 }\n:byte { X }"), [0x08]);
+    assert_eq!(rom(":calc X { ( 2 * 3 ) + 1 }\n:byte X"), [0x07]);
+}
+
+#[test]
+fn macros() {
+    let source = ":macro add-twice reg n { reg += n reg += n }\nadd-twice v2 3\nadd-twice v3 0x10";
+    assert_eq!(
+        rom(source),
+        [0x72, 0x03, 0x72, 0x03, 0x73, 0x10, 0x73, 0x10]
+    );
+}
+
+#[test]
+fn unpack() {
+    // Forward reference: v0 := 0xA2, v1 := 0x04 for `data` at 0x204
+    assert_eq!(
+        rom(":unpack 0xA data\n: data\n0x12"),
+        [0x60, 0xA2, 0x61, 0x04, 0x12]
+    );
+
+    // Backward reference
+    assert_eq!(
+        rom(": data\n0x12 0x34\n:unpack 0xB data"),
+        [0x12, 0x34, 0x60, 0xB2, 0x61, 0x00]
+    );
+}
+
+#[test]
+fn next_labels_the_operand_byte() {
+    // `target` is the low byte of `v3 := 0`, for self-modifying code
+    let program = assembler::assemble(":next target\nv3 := 0\ni := target").unwrap();
+    assert_eq!(program.labels["target"], 0x201);
+    assert_eq!(program.rom, [0x63, 0x00, 0xA2, 0x01]);
+}
+
+#[test]
+fn org() {
+    assert_eq!(
+        rom("jump later\n:org 0x208\n: later\nclear"),
+        [0x12, 0x08, 0, 0, 0, 0, 0, 0, 0x00, 0xE0]
+    );
+    assert_eq!(
+        error(":org 0x100\nclear"),
+        "line 2: cannot write below 0x200"
+    );
+}
+
+#[test]
+fn long_index() {
+    assert_eq!(rom("i := long 0x1234"), [0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(
+        rom("i := long data\n: data\n0xFF"),
+        [0xF0, 0x00, 0x02, 0x04, 0xFF]
+    );
+}
+
+#[test]
+fn main_and_symbol_map() {
+    let program = assembler::assemble(": helper\nreturn\n: main\nhelper").unwrap();
+
+    // A jump to `main` comes first
+    assert_eq!(program.rom, [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+    assert_eq!(program.symbol_map(), "0x0202 helper\n0x0204 main\n");
+}
+
+#[test]
+fn undefined_label() {
+    assert_eq!(
+        error("clear\njump nowhere"),
+        "line 2: undefined name nowhere"
+    );
+}
+
+#[test]
+fn duplicate_label() {
+    assert_eq!(
+        error(": again-label\nclear\n: again-label"),
+        "line 3: label again-label is already defined"
+    );
+}
+
+#[test]
+fn addresses_out_of_range() {
+    assert_eq!(error("jump 0x1000"), "line 1: address 4096 is out of range");
+    assert_eq!(error(":call -2"), "line 1: address -2 is out of range");
+    assert_eq!(
+        error("i := long 0x10000"),
+        "line 1: address 65536 is out of range"
+    );
+}
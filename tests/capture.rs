@@ -0,0 +1,119 @@
+//! Video recording: every emulated frame goes in, timed at 60 Hz.
+
+use std::{fs, path::PathBuf};
+
+use chipate::{
+    capture::{VideoFormat, VideoRecorder},
+    headless::{self, InputScript, Stop},
+    Framebuffer, Machine, Palette, Quirks, VideoSink,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chipate-{}-{}", std::process::id(), name))
+}
+
+/// Delays of every frame in a GIF, in hundredths of a second.
+fn gif_delays(gif: &[u8]) -> Vec<u16> {
+    assert_eq!(&gif[..6], b"GIF89a");
+    assert_eq!(&gif[6..10], [128, 0, 64, 0]);
+
+    let mut delays = Vec::new();
+    let mut i = 13 + 16 * 3;
+    loop {
+        match gif[i] {
+            0x21 => {
+                if gif[i + 1] == 0xF9 {
+                    delays.push(u16::from_le_bytes([gif[i + 4], gif[i + 5]]));
+                }
+                i += 2;
+            }
+            0x2C => i += 11,
+            0x3B => return delays,
+            b => panic!("unexpected block {:02x} at {}", b, i),
+        }
+
+        // Skip the data sub-blocks
+        while gif[i] != 0 {
+            i += gif[i] as usize + 1;
+        }
+        i += 1;
+    }
+}
+
+#[test]
+fn gif_length_follows_emulated_frames() {
+    let path = temp_path("timing.gif");
+    let mut video = VideoRecorder::create(&path, None, &Palette::default()).unwrap();
+
+    // A second of one picture, then a second of flicker every frame
+    let blank = Framebuffer::lores();
+    let mut lit = Framebuffer::lores();
+    lit.set(3, 3, 1);
+    for _ in 0..60 {
+        video.present(&blank);
+    }
+    for frame in 0..60 {
+        video.present(if frame % 2 == 0 { &lit } else { &blank });
+    }
+    assert_eq!(video.finish(), Ok(120));
+
+    let delays = gif_delays(&fs::read(&path).unwrap());
+    assert_eq!(delays[0], 100);
+    assert!(delays.iter().all(|&delay| delay >= 2), "{:?}", delays);
+    assert_eq!(delays.iter().map(|&d| d as u32).sum::<u32>(), 200);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn y4m_has_a_frame_per_emulated_frame() {
+    let path = temp_path("run.y4m");
+    let mut video = VideoRecorder::create(&path, None, &Palette::default()).unwrap();
+
+    // Counts up in V0 forever; fast enough that many instructions run per frame
+    let mut machine = Machine::new(100_000, false, Quirks::vip());
+    machine
+        .load_rom_bytes(&[0x60, 0x00, 0x70, 0x01, 0x12, 0x02])
+        .unwrap();
+    let report = headless::run_with_sink(&mut machine, 90, &mut InputScript::default(), &mut video);
+    assert_eq!(report.stop, Stop::Frames);
+    assert_eq!(video.finish(), Ok(90));
+
+    let y4m = fs::read(&path).unwrap();
+    let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+    assert!(y4m.starts_with(header));
+    assert_eq!(y4m.len(), header.len() + 90 * (6 + 128 * 64 * 3));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn frames_held_by_the_debugger_are_not_recorded() {
+    let path = temp_path("paused.y4m");
+    let mut video = VideoRecorder::create(&path, None, &Palette::default()).unwrap();
+
+    // Counting V0 to 50 reaches 0x206 after 149 instructions, partway into
+    // the 15th frame at 10 per frame. The breakpoint there holds that frame
+    // and every one after it
+    let mut machine = Machine::new(600, false, Quirks::vip());
+    machine
+        .load_rom_bytes(&[0x70, 0x01, 0x30, 0x32, 0x12, 0x00, 0x00, 0xE0, 0x12, 0x06])
+        .unwrap();
+    machine.debugger.toggle_breakpoint(0x206);
+    let report = headless::run_with_sink(&mut machine, 60, &mut InputScript::default(), &mut video);
+    assert_eq!(report.stop, Stop::Frames);
+    assert!(machine.debugger.is_paused());
+    assert_eq!(video.finish(), Ok(14));
+
+    let y4m = fs::read(&path).unwrap();
+    let frames = y4m.windows(6).filter(|w| w == b"FRAME\n").count();
+    assert_eq!(frames, 14);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn video_formats_come_from_the_extension() {
+    assert_eq!("GIF".parse(), Ok(VideoFormat::Gif));
+    assert!(VideoRecorder::create(&temp_path("clip.mp4"), None, &Palette::default()).is_err());
+}
@@ -0,0 +1,74 @@
+//! Conformance ROMs, assembled from `tests/roms` and run headless. Each
+//! compares the final framebuffer against a golden hash; the assertion
+//! message shows the frame that was drawn instead.
+
+use std::fs;
+
+use chipate::{
+    assembler,
+    headless::{self, InputScript, Stop},
+    image, Machine, Platform,
+};
+
+/// Assembles and runs `tests/roms/<name>.8o` until it halts in a loop.
+fn run(name: &str, platform: Platform) -> Machine {
+    let path = format!("{}/tests/roms/{}.8o", env!("CARGO_MANIFEST_DIR"), name);
+    let source = fs::read_to_string(&path).unwrap();
+    let program = assembler::assemble(&source).unwrap_or_else(|e| panic!("{}: {}", path, e));
+
+    let mut machine = Machine::new(1200, false, platform.quirks());
+    machine.load_rom_bytes(&program.rom).unwrap();
+
+    let report = headless::run(&mut machine, 600, &mut InputScript::default());
+    assert!(
+        matches!(report.stop, Stop::Loop { .. }),
+        "{} on {}: {}",
+        name,
+        platform,
+        report.stop
+    );
+    machine
+}
+
+fn check(name: &str, platform: Platform, golden: u64) {
+    let machine = run(name, platform);
+    let pixels = machine.pixels();
+    assert_eq!(
+        pixels.hash(),
+        golden,
+        "{} on {} drew:\n{}",
+        name,
+        platform,
+        image::ascii(pixels)
+    );
+}
+
+#[test]
+fn flags() {
+    for platform in [Platform::Vip, Platform::Schip, Platform::XoChip] {
+        check("flags", platform, 0x022e_2dd0_401e_f175);
+    }
+}
+
+#[test]
+fn memory() {
+    for platform in [Platform::Vip, Platform::Schip, Platform::XoChip] {
+        check("memory", platform, 0x8d49_83ea_92f0_e4dd);
+    }
+}
+
+#[test]
+fn sprites_clip() {
+    check("sprites", Platform::Vip, 0xc334_d47e_10c4_47d9);
+    check("sprites", Platform::Schip, 0xc334_d47e_10c4_47d9);
+}
+
+#[test]
+fn sprites_wrap() {
+    check("sprites", Platform::XoChip, 0x5715_8bf0_4a9b_f2ef);
+}
+
+#[test]
+fn scroll() {
+    check("scroll", Platform::Schip, 0xf31b_2120_3d0a_c845);
+}
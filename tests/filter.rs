@@ -0,0 +1,87 @@
+//! Display filters: blending successive frames into RGB output.
+
+use chipate::{
+    filter::{Filter, Persistence},
+    Framebuffer,
+};
+
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [200, 100, 50], [0, 0, 255], [255, 255, 255]];
+
+fn frame(lit: &[(usize, usize)]) -> Framebuffer {
+    let mut pixels = Framebuffer::lores();
+    for &(x, y) in lit {
+        pixels.set(x, y, 1);
+    }
+    pixels
+}
+
+/// RGB of the pixel at (x, y) in the last output.
+fn color(persistence: &Persistence, x: usize, y: usize) -> [u8; 3] {
+    let i = (y * 64 + x) * 3;
+    persistence.rgb()[i..i + 3].try_into().unwrap()
+}
+
+#[test]
+fn raw_shows_each_frame_as_drawn() {
+    let mut persistence = Persistence::new(Filter::Raw);
+    persistence.push(&frame(&[(1, 1)]), &PALETTE);
+    persistence.push(&frame(&[(2, 1)]), &PALETTE);
+
+    assert_eq!(color(&persistence, 1, 1), PALETTE[0]);
+    assert_eq!(color(&persistence, 2, 1), PALETTE[1]);
+    assert!(persistence.settled());
+}
+
+#[test]
+fn blend_ors_the_last_two_frames() {
+    let mut persistence = Persistence::new(Filter::Blend);
+    persistence.push(&frame(&[(1, 1)]), &PALETTE);
+    persistence.push(&frame(&[(2, 1)]), &PALETTE);
+
+    assert_eq!(color(&persistence, 1, 1), PALETTE[1]);
+    assert_eq!(color(&persistence, 2, 1), PALETTE[1]);
+    assert!(!persistence.settled());
+
+    // Once the same frame has been shown twice the old sprite is gone
+    persistence.push(&frame(&[(2, 1)]), &PALETTE);
+    assert_eq!(color(&persistence, 1, 1), PALETTE[0]);
+    assert!(persistence.settled());
+}
+
+#[test]
+fn phosphor_fades_out_and_settles() {
+    let mut persistence = Persistence::new(Filter::Phosphor);
+    persistence.push(&frame(&[(1, 1)]), &PALETTE);
+    assert_eq!(color(&persistence, 1, 1), PALETTE[1]);
+
+    assert!(persistence.settled());
+
+    let mut previous = PALETTE[1];
+    let mut frames = 0;
+    loop {
+        persistence.push(&frame(&[]), &PALETTE);
+        let now = color(&persistence, 1, 1);
+        assert!(now[0] < previous[0] || now == PALETTE[0], "{:?}", now);
+        previous = now;
+        frames += 1;
+        if persistence.settled() {
+            break;
+        }
+        assert!(frames < 60, "never settled");
+    }
+    assert_eq!(previous, PALETTE[0]);
+    assert!(frames > 2);
+}
+
+#[test]
+fn filters_parse_and_cycle() {
+    assert_eq!("blend".parse::<Filter>(), Ok(Filter::Blend));
+    assert_eq!("Phosphor".parse::<Filter>(), Ok(Filter::Phosphor));
+    assert!("crt".parse::<Filter>().is_err());
+
+    let mut filter = Filter::Raw;
+    for _ in 0..3 {
+        filter = filter.next();
+    }
+    assert_eq!(filter, Filter::Raw);
+}
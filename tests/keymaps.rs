@@ -0,0 +1,41 @@
+//! Keymap files: bad entries are rejected when loading, not when playing.
+#![cfg(feature = "keymaps")]
+
+use std::fs;
+
+use chipate::{keymaps::parse_keymap_file, Chip8Error};
+
+fn parse(name: &str, json: &str) -> Result<usize, Chip8Error> {
+    let path = std::env::temp_dir().join(format!("chipate-{}-{}.json", std::process::id(), name));
+    fs::write(&path, json).unwrap();
+    let result = parse_keymap_file(&path).map(|keymap| keymap.len());
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+#[test]
+fn keymap_entries_are_checked() {
+    assert_eq!(parse("ok", r#"[{ "scancode": 30, "key": 1 }]"#), Ok(1));
+
+    match parse("key", r#"[{ "scancode": 30, "key": 16 }]"#) {
+        Err(Chip8Error::Keymap { message, .. }) => {
+            assert!(message.contains("key 16"), "{}", message)
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        parse("json", r#"[{ "scancode": 30 }]"#),
+        Err(Chip8Error::Keymap { .. })
+    ));
+}
+
+#[cfg(feature = "sdl")]
+#[test]
+fn unknown_scancodes_are_rejected() {
+    match parse("scancode", r#"[{ "scancode": 100000, "key": 1 }]"#) {
+        Err(Chip8Error::Keymap { message, .. }) => {
+            assert!(message.contains("scancode 100000"), "{}", message)
+        }
+        other => panic!("{:?}", other),
+    }
+}
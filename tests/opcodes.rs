@@ -0,0 +1,586 @@
+//! Per-opcode tests for `Cpu::tick`, run under every platform's quirks.
+
+use chipate::{cpu::Cpu, Chip8Error, Platform, Quirks, RngMode};
+
+const PLATFORMS: [Platform; 3] = [Platform::Vip, Platform::Schip, Platform::XoChip];
+
+/// A CPU with `program` loaded at 0x200.
+fn load(quirks: Quirks, program: &[u16]) -> Cpu {
+    let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    let mut cpu = Cpu::new(60, quirks);
+    cpu.load_rom_bytes(&rom).unwrap();
+    cpu
+}
+
+/// Executes `program`, one tick per opcode.
+fn run(quirks: Quirks, program: &[u16]) -> Cpu {
+    let mut cpu = load(quirks, program);
+    for _ in program {
+        cpu.tick().unwrap();
+    }
+    cpu
+}
+
+/// Runs `program` under every platform.
+fn each_platform(program: &[u16], check: impl Fn(Platform, &Cpu)) {
+    for platform in PLATFORMS {
+        check(platform, &run(platform.quirks(), program));
+    }
+}
+
+#[test]
+fn clear_screen() {
+    each_platform(&[0xA000, 0xD005, 0x00E0], |_, cpu| {
+        assert!(cpu.pixels.rows().flatten().all(|&p| p == 0));
+    });
+}
+
+#[test]
+fn jump() {
+    let mut cpu = load(Quirks::vip(), &[0x1234]);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x234);
+}
+
+#[test]
+fn call_and_return() {
+    // 200: call 206, 202: v0 := 1, 204: halt, 206: v1 := 2, 208: return
+    let mut cpu = load(Quirks::vip(), &[0x2206, 0x6001, 0x1204, 0x6102, 0x00EE]);
+    for _ in 0..4 {
+        cpu.tick().unwrap();
+    }
+    assert_eq!(cpu.pc(), 0x204);
+    assert_eq!(cpu.sp(), 0);
+    assert_eq!(cpu.vreg()[..2], [1, 2]);
+}
+
+#[test]
+fn stack_depth() {
+    // Calls itself until the stack is full
+    let mut cpu = load(Quirks::vip(), &[0x2200]);
+    for depth in 1..=16 {
+        cpu.tick().unwrap();
+        assert_eq!(cpu.sp(), depth);
+    }
+    assert_eq!(cpu.stack(), [0x200; 16]);
+    assert_eq!(cpu.tick(), Err(Chip8Error::StackOverflow { pc: 0x200 }));
+    assert_eq!(cpu.sp(), 16);
+}
+
+#[test]
+fn return_with_empty_stack() {
+    let mut cpu = load(Quirks::vip(), &[0x00EE]);
+    assert_eq!(cpu.tick(), Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+    assert_eq!(cpu.pc(), 0x200);
+}
+
+#[test]
+fn skips() {
+    // Each skip is followed by vE := 1; skipped if vE stays 0
+    let skipped = |program: &[u16]| {
+        let mut program = program.to_vec();
+        program.push(0x6E01);
+        let mut cpu = load(Quirks::vip(), &program);
+        for _ in 0..program.len() - 1 {
+            cpu.tick().unwrap();
+        }
+        cpu.vreg()[0xE] == 0 && cpu.pc() == 0x200 + 2 * program.len() as u16
+    };
+
+    assert!(skipped(&[0x6005, 0x3005]));
+    assert!(!skipped(&[0x6005, 0x3006, 0x6F00]));
+    assert!(skipped(&[0x6005, 0x4006]));
+    assert!(!skipped(&[0x6005, 0x4005, 0x6F00]));
+    assert!(skipped(&[0x6005, 0x6105, 0x5010]));
+    assert!(!skipped(&[0x6005, 0x6106, 0x5010, 0x6F00]));
+    assert!(skipped(&[0x6005, 0x6106, 0x9010]));
+    assert!(!skipped(&[0x6005, 0x6105, 0x9010, 0x6F00]));
+}
+
+#[test]
+fn skip_over_long_load() {
+    // A skip jumps the whole 4-byte F000 NNNN
+    let mut cpu = load(Quirks::xochip(), &[0x3000, 0xF000, 0x1234]);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x206);
+}
+
+#[test]
+fn load_and_add_immediate() {
+    each_platform(&[0x6AFE, 0x7A03, 0x6F07, 0x7F01], |_, cpu| {
+        // 7XNN wraps and never touches VF
+        assert_eq!(cpu.vreg()[0xA], 0x01);
+        assert_eq!(cpu.vreg()[0xF], 0x08);
+    });
+}
+
+#[test]
+fn logic_ops() {
+    for (op, result) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+        each_platform(&[0x600C, 0x610A, 0x6F05, op], |platform, cpu| {
+            assert_eq!(cpu.vreg()[0], result, "{:04X}", op);
+            let vf = if platform.quirks().vf_reset { 0 } else { 5 };
+            assert_eq!(cpu.vreg()[0xF], vf, "{:04X} on {}", op, platform);
+        });
+    }
+}
+
+#[test]
+fn add_sets_carry() {
+    each_platform(&[0x60FF, 0x6102, 0x8014], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 0x01);
+        assert_eq!(cpu.vreg()[0xF], 1);
+    });
+    each_platform(&[0x6010, 0x6102, 0x6F01, 0x8014], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 0x12);
+        assert_eq!(cpu.vreg()[0xF], 0);
+    });
+}
+
+#[test]
+fn sub_sets_not_borrow() {
+    each_platform(&[0x6005, 0x6103, 0x8015], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 2);
+        assert_eq!(cpu.vreg()[0xF], 1);
+    });
+    each_platform(&[0x6003, 0x6105, 0x8015], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 0xFE);
+        assert_eq!(cpu.vreg()[0xF], 0);
+    });
+    // Equal operands don't borrow
+    each_platform(&[0x6007, 0x6107, 0x8015], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 0);
+        assert_eq!(cpu.vreg()[0xF], 1);
+    });
+}
+
+#[test]
+fn sub_reverse_sets_not_borrow() {
+    each_platform(&[0x6003, 0x6105, 0x8017], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 2);
+        assert_eq!(cpu.vreg()[0xF], 1);
+    });
+    each_platform(&[0x6005, 0x6103, 0x8017], |_, cpu| {
+        assert_eq!(cpu.vreg()[0], 0xFE);
+        assert_eq!(cpu.vreg()[0xF], 0);
+    });
+}
+
+#[test]
+fn flag_wins_when_vf_is_the_target() {
+    // 8FY4/8FY5/8FY7/8FY6/8FYE: VF ends up holding the flag, not the result
+    for (op, vf) in [
+        (0x8F04, 1), // 0xFF + 0x01 carries
+        (0x8F05, 1), // 0xFF - 0x01 doesn't borrow
+        (0x8F07, 0), // 0x01 - 0xFF borrows
+        (0x8F06, 1), // 0xFF >> 1 shifts out a 1
+        (0x8F0E, 1), // 0xFF << 1 shifts out a 1
+    ] {
+        each_platform(&[0x6FFF, 0x6001, 0x6001, op], |platform, cpu| {
+            // The shifts read V0 (= 1) instead of VF with the shift_vy quirk
+            let vf = match op {
+                0x8F06 => 1,
+                0x8F0E if platform.quirks().shift_vy => 0,
+                _ => vf,
+            };
+            assert_eq!(cpu.vreg()[0xF], vf, "{:04X} on {}", op, platform);
+        });
+    }
+}
+
+#[test]
+fn shifts() {
+    each_platform(&[0x6081, 0x6106, 0x8016], |platform, cpu| {
+        if platform.quirks().shift_vy {
+            assert_eq!(cpu.vreg()[0], 0x03);
+            assert_eq!(cpu.vreg()[0xF], 0);
+        } else {
+            assert_eq!(cpu.vreg()[0], 0x40);
+            assert_eq!(cpu.vreg()[0xF], 1);
+        }
+    });
+    each_platform(&[0x6081, 0x6106, 0x801E], |platform, cpu| {
+        if platform.quirks().shift_vy {
+            assert_eq!(cpu.vreg()[0], 0x0C);
+            assert_eq!(cpu.vreg()[0xF], 0);
+        } else {
+            assert_eq!(cpu.vreg()[0], 0x02);
+            assert_eq!(cpu.vreg()[0xF], 1);
+        }
+    });
+}
+
+#[test]
+fn jump_with_offset() {
+    for platform in PLATFORMS {
+        let mut cpu = load(platform.quirks(), &[0x6004, 0x6308, 0xB310]);
+        for _ in 0..3 {
+            cpu.tick().unwrap();
+        }
+        let expected = if platform.quirks().jump_vx {
+            0x318
+        } else {
+            0x314
+        };
+        assert_eq!(cpu.pc(), expected, "{}", platform);
+    }
+}
+
+#[test]
+fn random_is_masked_and_seeded() {
+    let program = [0xC0FF, 0xC1FF, 0xC2FF, 0xC30F, 0xC400];
+    let seeded = |seed| {
+        let mut cpu = load(Quirks::vip(), &program);
+        cpu.seed_rng(seed, RngMode::SplitMix);
+        for _ in program {
+            cpu.tick().unwrap();
+        }
+        *cpu.vreg()
+    };
+
+    let vreg = seeded(7);
+    assert_eq!(vreg, seeded(7));
+    assert_ne!(vreg[..3], seeded(8)[..3]);
+    assert_eq!(vreg[3] & 0xF0, 0);
+    assert_eq!(vreg[4], 0);
+}
+
+#[test]
+fn draw_and_collision() {
+    each_platform(&[0x6000, 0xA000, 0xD005], |_, cpu| {
+        // Font digit 0: F0 90 90 90 F0
+        assert_eq!(cpu.pixels.get(0, 0), 1);
+        assert_eq!(cpu.pixels.get(4, 0), 0);
+        assert_eq!(cpu.pixels.get(1, 1), 0);
+        assert_eq!(cpu.vreg()[0xF], 0);
+    });
+
+    for platform in PLATFORMS {
+        let mut cpu = load(platform.quirks(), &[0x6000, 0xA000, 0xD005, 0xD005]);
+        for _ in 0..3 {
+            cpu.tick().unwrap();
+        }
+        cpu.vblank();
+        cpu.tick().unwrap();
+        assert!(cpu.pixels.rows().flatten().all(|&p| p == 0));
+        assert_eq!(cpu.vreg()[0xF], 1, "{}", platform);
+    }
+}
+
+#[test]
+fn sprites_wrap_or_clip_at_the_edge() {
+    // Digit 0's top row (F0) drawn at x = 62 covers columns 62, 63, 0 and 1
+    each_platform(&[0x603E, 0x6100, 0xA000, 0xD011], |platform, cpu| {
+        assert_eq!(cpu.pixels.get(63, 0), 1);
+        let wrapped = if platform.quirks().clip { 0 } else { 1 };
+        assert_eq!(cpu.pixels.get(0, 0), wrapped, "{}", platform);
+        assert_eq!(cpu.pixels.get(1, 0), wrapped, "{}", platform);
+    });
+
+    // The starting position always wraps
+    each_platform(&[0x6042, 0x6121, 0xA000, 0xD011], |_, cpu| {
+        assert_eq!(cpu.pixels.get(2, 1), 1);
+    });
+}
+
+#[test]
+fn display_wait() {
+    for platform in PLATFORMS {
+        let mut cpu = load(platform.quirks(), &[0xA000, 0xD005, 0xD005]);
+        for _ in 0..3 {
+            cpu.tick().unwrap();
+        }
+        let pc = if platform.quirks().display_wait {
+            0x204
+        } else {
+            0x206
+        };
+        assert_eq!(cpu.pc(), pc, "{}", platform);
+    }
+}
+
+#[test]
+fn key_skips() {
+    let mut cpu = load(Quirks::vip(), &[0x601F, 0xE09E, 0x0000, 0xE0A1]);
+    cpu.keypad[0xF] = 1;
+    for _ in 0..2 {
+        cpu.tick().unwrap();
+    }
+    // Only the low nibble of Vx selects the key
+    assert_eq!(cpu.pc(), 0x206);
+
+    cpu.keypad[0xF] = 0;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x20A);
+}
+
+#[test]
+fn wait_for_key_blocks_until_release() {
+    let mut cpu = load(Quirks::vip(), &[0xF30A]);
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+        assert_eq!(cpu.pc(), 0x200);
+        assert!(cpu.waiting_for_key());
+    }
+
+    // Key F counts too; the key is reported once it is released
+    cpu.keypad[0xF] = 1;
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+
+    cpu.keypad[0xF] = 0;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.vreg()[3], 0xF);
+    assert!(!cpu.waiting_for_key());
+}
+
+#[test]
+fn wait_for_key_ignores_other_releases() {
+    let mut cpu = load(Quirks::vip(), &[0xF00A]);
+    cpu.keypad[0x5] = 1;
+    cpu.tick().unwrap();
+
+    // Another key coming and going doesn't end the wait
+    cpu.keypad[0x7] = 1;
+    cpu.tick().unwrap();
+    cpu.keypad[0x7] = 0;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+
+    cpu.keypad[0x5] = 0;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.vreg()[0], 0x5);
+}
+
+#[test]
+fn timers_run_while_waiting_for_key() {
+    let mut cpu = load(Quirks::vip(), &[0x6005, 0xF015, 0xF00A]);
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+    }
+    cpu.tick_timers();
+    assert!(cpu.waiting_for_key());
+    assert_eq!(cpu.delay_timer(), 4);
+}
+
+#[test]
+fn timers() {
+    each_platform(&[0x6020, 0xF015, 0xF018, 0xF207], |_, cpu| {
+        assert_eq!(cpu.delay_timer(), 0x20);
+        assert_eq!(cpu.sound_timer, 0x20);
+        assert_eq!(cpu.vreg()[2], 0x20);
+    });
+}
+
+#[test]
+fn add_to_index() {
+    // FX1E leaves VF alone, even when I goes past 0xFFF
+    let overflow = [0xAFFF, 0x6002, 0x6F07, 0xF01E];
+    each_platform(&overflow, |_, cpu| {
+        assert_eq!(cpu.ireg(), 0x1001);
+        assert_eq!(cpu.vreg()[0xF], 7);
+    });
+
+    // unless the index_overflow quirk is set, which makes VF the overflow flag
+    for platform in PLATFORMS {
+        let quirks = Quirks {
+            index_overflow: true,
+            ..platform.quirks()
+        };
+        let cpu = run(quirks, &overflow);
+        assert_eq!(cpu.ireg(), 0x1001);
+        assert_eq!(cpu.vreg()[0xF], 1, "{}", platform);
+
+        let cpu = run(quirks, &[0xAFFE, 0x6001, 0x6F07, 0xF01E]);
+        assert_eq!(cpu.ireg(), 0xFFF);
+        assert_eq!(cpu.vreg()[0xF], 0, "{}", platform);
+    }
+}
+
+#[test]
+fn font() {
+    each_platform(&[0x6007, 0xF029, 0x61F7, 0xF129], |_, cpu| {
+        assert_eq!(cpu.ireg(), 35);
+    });
+    each_platform(&[0x6003, 0xF030], |_, cpu| {
+        assert_eq!(cpu.ireg(), 80 + 30);
+    });
+}
+
+#[test]
+fn bcd() {
+    each_platform(&[0x60FE, 0xA300, 0xF033], |_, cpu| {
+        assert_eq!(
+            [cpu.read(0x300), cpu.read(0x301), cpu.read(0x302)],
+            [2, 5, 4]
+        );
+        assert_eq!(cpu.ireg(), 0x300);
+    });
+    each_platform(&[0x6007, 0xA300, 0xF033], |_, cpu| {
+        assert_eq!(
+            [cpu.read(0x300), cpu.read(0x301), cpu.read(0x302)],
+            [0, 0, 7]
+        );
+    });
+}
+
+#[test]
+fn store_and_load() {
+    let program = [0x6011, 0x6122, 0x6233, 0xA300, 0xF255, 0xA300, 0xF165];
+    each_platform(&program, |platform, cpu| {
+        assert_eq!(
+            [cpu.read(0x300), cpu.read(0x301), cpu.read(0x302)],
+            [0x11, 0x22, 0x33]
+        );
+        let ireg = if platform.quirks().load_store_increment {
+            0x302
+        } else {
+            0x300
+        };
+        assert_eq!(cpu.ireg(), ireg, "{}", platform);
+    });
+
+    // FX65 only touches V0 through Vx
+    let program = [0xA000, 0x6177, 0xF065];
+    each_platform(&program, |_, cpu| {
+        assert_eq!(cpu.vreg()[..2], [0xF0, 0x77]);
+    });
+}
+
+#[test]
+fn store_and_load_ranges() {
+    // 5XY2 saves V1..V3, 5XY3 loads them back reversed into V6..V4
+    let program = [0x6111, 0x6222, 0x6333, 0xA300, 0x5132, 0x5643];
+    let cpu = run(Quirks::xochip(), &program);
+    assert_eq!(
+        [cpu.read(0x300), cpu.read(0x301), cpu.read(0x302)],
+        [0x11, 0x22, 0x33]
+    );
+    assert_eq!(cpu.vreg()[4..7], [0x33, 0x22, 0x11]);
+    assert_eq!(cpu.ireg(), 0x300);
+}
+
+#[test]
+fn flags() {
+    each_platform(
+        &[0x6012, 0x6134, 0xF175, 0x6000, 0x6100, 0xF085],
+        |_, cpu| {
+            assert_eq!(cpu.vreg()[..2], [0x12, 0x00]);
+        },
+    );
+}
+
+#[test]
+fn long_load() {
+    let mut cpu = load(Quirks::xochip(), &[0xF000, 0xBEEF]);
+    cpu.tick().unwrap();
+    assert_eq!(cpu.ireg(), 0xBEEF);
+    assert_eq!(cpu.pc(), 0x204);
+}
+
+#[test]
+fn planes() {
+    // Plane 2 only: drawing sets bit 1 of the pixel
+    let cpu = run(Quirks::xochip(), &[0xF201, 0xA000, 0xD001]);
+    assert_eq!(cpu.pixels.get(0, 0), 0b10);
+}
+
+#[test]
+fn resolution_and_scrolling() {
+    let cpu = run(Quirks::schip(), &[0x00FF, 0xA000, 0xD001, 0x00C2, 0x00FB]);
+    assert!(cpu.pixels.is_hires());
+    assert_eq!(cpu.pixels.get(4, 2), 1);
+    assert_eq!(cpu.pixels.get(0, 0), 0);
+
+    let cpu = run(Quirks::schip(), &[0x00FF, 0x00FE]);
+    assert!(!cpu.pixels.is_hires());
+}
+
+#[test]
+fn exit_halts() {
+    let mut cpu = load(Quirks::schip(), &[0x00FD, 0x6001]);
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert!(cpu.halted);
+    assert_eq!(cpu.vreg()[0], 0);
+}
+
+#[test]
+fn unknown_opcode_faults() {
+    let mut cpu = load(Quirks::vip(), &[0x5001]);
+    assert_eq!(
+        cpu.tick(),
+        Err(Chip8Error::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x5001
+        })
+    );
+    assert_eq!(cpu.pc(), 0x200);
+}
+
+#[test]
+fn memory_faults_leave_the_cpu_unchanged() {
+    let mut cpu = load(Quirks::xochip(), &[0xF000, 0xFFFE, 0xF255]);
+    cpu.tick().unwrap();
+    assert!(matches!(
+        cpu.tick(),
+        Err(Chip8Error::MemoryFault { pc: 0x204, .. })
+    ));
+    assert_eq!(cpu.pc(), 0x204);
+    assert_eq!(cpu.read(0xFFFE), 0);
+}
+
+#[test]
+fn memory_ends_at_4k_except_on_xochip() {
+    // I := 0xFFF; v0 := 0; save v1
+    let program = [0xAFFF, 0x6000, 0xF155];
+    for platform in PLATFORMS {
+        let mut cpu = load(platform.quirks(), &program);
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        let result = cpu.tick();
+        if platform == Platform::XoChip {
+            assert_eq!(result, Ok(()));
+        } else {
+            assert_eq!(
+                result,
+                Err(Chip8Error::MemoryFault {
+                    pc: 0x204,
+                    addr: 0x1000
+                }),
+                "{}",
+                platform
+            );
+        }
+    }
+}
+
+#[test]
+fn switching_platforms_resizes_memory() {
+    let mut cpu = load(Quirks::vip(), &[0x1234]);
+    cpu.set_quirks(Quirks::xochip());
+    assert_eq!(cpu.memory_size(), 0x10000);
+    assert_eq!(cpu.read_word(0x200), 0x1234);
+
+    cpu.set_quirks(Quirks::schip());
+    assert_eq!(cpu.memory_size(), 0x1000);
+    assert_eq!(cpu.read_word(0x200), 0x1234);
+}
+
+#[test]
+fn rom_size_is_limited_by_memory() {
+    for (platform, max) in [
+        (Platform::Vip, 3584),
+        (Platform::Schip, 3584),
+        (Platform::XoChip, 65024),
+    ] {
+        let mut cpu = Cpu::new(60, platform.quirks());
+        assert_eq!(cpu.load_rom_bytes(&vec![0; max]), Ok(()));
+        assert_eq!(
+            cpu.load_rom_bytes(&vec![0; max + 1]),
+            Err(Chip8Error::RomTooLarge { size: max + 1, max })
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Palette presets, palette files and color parsing.
+
+use chipate::{
+    palette::{parse_color, MAX_COLORS},
+    Chip8Error, Palette,
+};
+
+#[test]
+fn colors_parse_in_long_and_short_form() {
+    assert_eq!(parse_color("#8bac0f"), Ok([0x8b, 0xac, 0x0f]));
+    assert_eq!(parse_color("8BAC0F"), Ok([0x8b, 0xac, 0x0f]));
+    assert_eq!(parse_color("#f80"), Ok([0xff, 0x88, 0x00]));
+
+    for bad in [
+        "", "#", "#12345", "##123456", "#12345g", "+12345", "#1234567",
+    ] {
+        assert_eq!(
+            parse_color(bad),
+            Err(Chip8Error::InvalidColor(bad.to_string())),
+            "{:?}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn presets_are_complete() {
+    let presets = Palette::presets();
+    assert_eq!(presets.len(), 7);
+    for palette in &presets {
+        assert_eq!(palette.colors().len(), 4, "{}", palette.name);
+        assert_eq!(Palette::find(&palette.name).as_ref(), Ok(palette));
+    }
+
+    // The default is the original Game Boy green
+    assert_eq!(Palette::default().colors()[0], [0x0f, 0x38, 0x0f]);
+    assert!(Palette::find("no-such-palette").is_err());
+}
+
+#[test]
+fn palette_files_list_colors_by_index() {
+    let palette = Palette::parse(
+        "dusk",
+        "; Dusk\n#1b1b3a  background\n\n#f4d35e plane 1\n#ee964b\n",
+    )
+    .unwrap();
+    assert_eq!(
+        palette.colors(),
+        [[0x1b, 0x1b, 0x3a], [0xf4, 0xd3, 0x5e], [0xee, 0x96, 0x4b]]
+    );
+
+    // Pixel values without a color of their own use plane 1's
+    let table = palette.table();
+    assert_eq!(table.len(), MAX_COLORS);
+    assert_eq!(table[2], [0xee, 0x96, 0x4b]);
+    assert_eq!(table[3], [0xf4, 0xd3, 0x5e]);
+
+    assert!(Palette::parse("bad", "#000000\nnot a color\n")
+        .unwrap_err()
+        .starts_with("line 2"));
+    assert!(Palette::parse("short", "#000000\n").is_err());
+    assert!(Palette::parse("long", &"#000000\n".repeat(17)).is_err());
+}
+
+#[test]
+fn overriding_a_color_grows_the_palette() {
+    let mut palette = Palette::parse("pair", "#000\n#fff\n").unwrap();
+    palette.set(3, [1, 2, 3]);
+    assert_eq!(
+        palette.colors(),
+        [[0, 0, 0], [255, 255, 255], [255, 255, 255], [1, 2, 3]]
+    );
+}
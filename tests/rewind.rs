@@ -0,0 +1,99 @@
+//! Rewind history: every snapshot popped back must equal the one pushed,
+//! including after old snapshots were evicted.
+
+use chipate::{rewind::Rewind, state::CpuState, Machine, Quirks};
+
+/// Writes a different byte to a different address every frame, so each
+/// snapshot's memory differs from the next.
+fn machine() -> Machine {
+    let program: [u16; 5] = [
+        0x7001, // v0 += 1
+        0xA300, // i := 0x300
+        0xF01E, // i += v0
+        0xF055, // save v0
+        0x1200, // jump 0x200
+    ];
+    let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+
+    // One pass through the loop per frame
+    let mut machine = Machine::new(300, false, Quirks::vip());
+    machine.load_rom_bytes(&rom).unwrap();
+    machine
+}
+
+/// Runs `frames` frames, pushing each into `rewind`. Returns the snapshot
+/// taken every `interval` frames, oldest first.
+fn record(rewind: &mut Rewind, frames: u32, interval: u32) -> Vec<CpuState> {
+    let mut machine = machine();
+    let mut snapshots = Vec::new();
+
+    for frame in 1..=frames {
+        machine.frame().unwrap();
+        rewind.push(&machine.cpu);
+        if frame % interval == 0 {
+            snapshots.push(machine.cpu.snapshot());
+        }
+    }
+    snapshots
+}
+
+/// Pops everything left in `rewind`, newest first.
+fn pop_all(rewind: &mut Rewind) -> Vec<CpuState> {
+    std::iter::from_fn(|| rewind.pop()).collect()
+}
+
+#[test]
+fn pops_every_snapshot_back() {
+    let mut rewind = Rewind::new(10, 1, usize::MAX);
+    let snapshots = record(&mut rewind, 50, 1);
+
+    // The newest snapshot is the current state; popping steps back from it
+    let popped = pop_all(&mut rewind);
+    assert_eq!(popped.len(), 49);
+    for (i, (popped, pushed)) in popped
+        .iter()
+        .zip(snapshots.iter().rev().skip(1))
+        .enumerate()
+    {
+        assert!(popped == pushed, "snapshot {} back differs", i + 1);
+    }
+}
+
+#[test]
+fn keeps_the_newest_snapshots_within_the_byte_budget() {
+    let max_bytes = 12_000;
+    let mut rewind = Rewind::new(10, 1, max_bytes);
+    let snapshots = record(&mut rewind, 50, 1);
+    assert!(rewind.memory_usage() <= max_bytes);
+
+    let popped = pop_all(&mut rewind);
+    assert!(!popped.is_empty() && popped.len() < 49, "{}", popped.len());
+    for (i, (popped, pushed)) in popped
+        .iter()
+        .zip(snapshots.iter().rev().skip(1))
+        .enumerate()
+    {
+        assert!(popped == pushed, "snapshot {} back differs", i + 1);
+    }
+}
+
+#[test]
+fn keeps_the_newest_snapshots_within_the_time_limit() {
+    // 1 second at one snapshot every 10 frames: 6 older snapshots
+    let mut rewind = Rewind::new(1, 10, usize::MAX);
+    let snapshots = record(&mut rewind, 200, 10);
+    assert_eq!(rewind.seconds(), 1.0);
+
+    let popped = pop_all(&mut rewind);
+    assert_eq!(popped.len(), 6);
+    for (popped, pushed) in popped.iter().zip(snapshots.iter().rev().skip(1)) {
+        assert!(popped == pushed);
+    }
+}
+
+#[test]
+fn huge_limits_do_not_overflow() {
+    let mut rewind = Rewind::new(u32::MAX, 1, usize::MAX);
+    record(&mut rewind, 3, 1);
+    assert_eq!(pop_all(&mut rewind).len(), 2);
+}
@@ -0,0 +1,82 @@
+//! Screenshots: scaling, timestamps and the PNG files written.
+
+use std::{
+    fs,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chipate::{
+    image,
+    screenshot::{timestamp, Screenshots},
+    Framebuffer, Palette,
+};
+
+/// Width and height from a PNG's IHDR chunk.
+fn png_size(png: &[u8]) -> (u32, u32) {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+    (width, height)
+}
+
+#[test]
+fn timestamps_are_utc_calendar_dates() {
+    assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000");
+    assert_eq!(
+        timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723)),
+        "20000229-010203"
+    );
+    assert_eq!(
+        timestamp(UNIX_EPOCH + Duration::from_secs(1_792_367_999)),
+        "20261018-235959"
+    );
+}
+
+#[test]
+fn scaling_repeats_each_pixel() {
+    let mut pixels = Framebuffer::lores();
+    pixels.set(1, 0, 3);
+
+    let scaled = image::scale(&pixels, 3);
+    assert_eq!((scaled.width(), scaled.height()), (192, 96));
+    for y in 0..3 {
+        assert_eq!(
+            (2..7).map(|x| scaled.get(x, y)).collect::<Vec<_>>(),
+            [0, 3, 3, 3, 0]
+        );
+    }
+    assert_eq!(scaled.get(3, 3), 0);
+}
+
+#[test]
+fn screenshots_are_saved_at_native_and_scaled_size() {
+    let dir = std::env::temp_dir().join(format!("chipate-screenshots-{}", std::process::id()));
+    let screenshots = Screenshots::new(&dir, 4);
+
+    let mut pixels = Framebuffer::new(128, 64);
+    pixels.set(0, 0, 1);
+    let palette = Palette::preset("octo").unwrap();
+
+    let first = screenshots.save("pong", &pixels, &palette).unwrap();
+    let second = screenshots.save("pong", &pixels, &palette).unwrap();
+    assert_eq!(first.len(), 2);
+    assert_ne!(
+        first[0], second[0],
+        "same-second screenshots overwrote each other"
+    );
+
+    let native = fs::read(&first[0]).unwrap();
+    let scaled = fs::read(&first[1]).unwrap();
+    assert_eq!(png_size(&native), (128, 64));
+    assert_eq!(png_size(&scaled), (512, 256));
+    assert_eq!(native, image::png(&pixels, &palette.table()));
+
+    let name = first[1].file_name().unwrap().to_string_lossy().into_owned();
+    assert!(
+        name.starts_with("pong-") && name.ends_with("-4x.png"),
+        "{}",
+        name
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,113 @@
+//! Save state validation: damaged snapshots must be rejected, not restored.
+
+use chipate::{cpu::Cpu, cpu::KeyWait, framebuffer::Framebuffer, state::CpuState, Quirks};
+
+fn snapshot() -> CpuState {
+    let mut cpu = Cpu::new(60, Quirks::default());
+    cpu.load_rom_bytes(&[0x12, 0x00]).unwrap();
+    cpu.snapshot()
+}
+
+/// Applies `corrupt` to a good snapshot and expects `validate` to fail.
+fn rejects(field: &str, corrupt: impl Fn(&mut CpuState)) {
+    let mut state = snapshot();
+    corrupt(&mut state);
+    assert!(state.validate().is_err(), "accepted a corrupted {}", field);
+}
+
+#[test]
+fn good_snapshots_validate() {
+    assert_eq!(snapshot().validate(), Ok(()));
+
+    let mut state = snapshot();
+    state.sp = 16;
+    state.planes = 0b11;
+    state.key_wait = KeyWait::Release(0xF);
+    state.pixels = Framebuffer::new(128, 64);
+    assert_eq!(state.validate(), Ok(()));
+}
+
+#[test]
+fn corrupted_fields_are_rejected() {
+    rejects("memory", |state| state.memory.truncate(100));
+    rejects("sp", |state| state.sp = 17);
+    rejects("planes", |state| state.planes = 4);
+    rejects("key_wait", |state| state.key_wait = KeyWait::Release(16));
+    rejects("pixels", |state| state.pixels = Framebuffer::new(65, 32));
+    rejects("pixels", |state| state.pixels = Framebuffer::new(0, 0));
+}
+
+#[cfg(feature = "savestate")]
+mod files {
+    use std::{fs, path::PathBuf};
+
+    use chipate::{cpu::Cpu, state, Quirks};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chipate-{}-{}.state", std::process::id(), name))
+    }
+
+    fn cpu() -> Cpu {
+        let mut cpu = Cpu::new(60, Quirks::default());
+        cpu.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        cpu
+    }
+
+    /// Saves a state, lets `corrupt` edit the file and loads it back.
+    fn load_corrupted(name: &str, corrupt: impl Fn(&mut Vec<u8>)) -> Result<(), String> {
+        let path = temp_path(name);
+        state::save(&cpu(), &path).unwrap();
+
+        let mut buf = fs::read(&path).unwrap();
+        corrupt(&mut buf);
+        fs::write(&path, buf).unwrap();
+
+        let mut target = cpu();
+        let before = target.snapshot();
+        let result = state::load(&mut target, &path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(target.snapshot(), before, "{} touched the CPU", name);
+        result
+    }
+
+    #[test]
+    fn loads_untouched_file() {
+        assert_eq!(load_corrupted("untouched", |_| {}), Ok(()));
+    }
+
+    #[test]
+    fn rejects_state_from_platform_with_other_memory_size() {
+        let path = temp_path("platform");
+        let mut xochip = Cpu::new(60, Quirks::xochip());
+        xochip.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        state::save(&xochip, &path).unwrap();
+
+        let result = state::load(&mut cpu(), &path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_framebuffer_of_wrong_length() {
+        // The file ends with the 64x32 pixels, prefixed by their u64 count,
+        // and the 16 keypad bytes. Drop one pixel and fix up the count.
+        let result = load_corrupted("pixels", |buf| {
+            let pixels_end = buf.len() - 16;
+            let count_at = pixels_end - 64 * 32 - 8;
+            buf[count_at..count_at + 8].copy_from_slice(&(64u64 * 32 - 1).to_le_bytes());
+            buf.remove(pixels_end - 1);
+        });
+        assert!(result.unwrap_err().contains("corrupted"));
+    }
+
+    #[test]
+    fn rejects_framebuffer_with_wrong_dimensions() {
+        // Width, then height, precede the pixel count
+        let result = load_corrupted("dimensions", |buf| {
+            let width_at = buf.len() - 16 - 64 * 32 - 8 * 3;
+            buf[width_at..width_at + 8].copy_from_slice(&128u64.to_le_bytes());
+        });
+        assert!(result.unwrap_err().contains("corrupted"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Frame clock: instruction budgets and 60 Hz timers.
+
+use chipate::{
+    consts::{MAX_HZ, MIN_HZ},
+    cpu::Cpu,
+    Machine, Quirks,
+};
+
+#[test]
+fn frame_budget_carries_the_remainder() {
+    let mut cpu = Cpu::new(500, Quirks::vip());
+    let budgets: Vec<u32> = (0..6).map(|_| cpu.frame_budget()).collect();
+    assert_eq!(budgets, [8, 8, 9, 8, 8, 9]);
+
+    // Over a second, exactly `hz` instructions run
+    let mut cpu = Cpu::new(700, Quirks::vip());
+    assert_eq!((0..60).map(|_| cpu.frame_budget()).sum::<u32>(), 700);
+
+    let mut cpu = Cpu::new(30, Quirks::vip());
+    let budgets: Vec<u32> = (0..4).map(|_| cpu.frame_budget()).collect();
+    assert_eq!(budgets, [0, 1, 0, 1]);
+}
+
+/// A machine running `delay := 60`, `buzzer := 60`, then looping.
+fn timers_machine(hz: u32) -> Machine {
+    let mut machine = Machine::new(hz, false, Quirks::vip());
+    machine
+        .load_rom_bytes(&[0x60, 0x3C, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06])
+        .unwrap();
+    machine
+}
+
+#[test]
+fn timers_count_emulated_frames_at_any_speed() {
+    for hz in [180, 600, 100_000] {
+        let mut machine = timers_machine(hz);
+
+        // The first frame sets the timers and ticks them once
+        machine.frame().unwrap();
+        assert_eq!(machine.cpu.delay_timer(), 59, "{} Hz", hz);
+        assert_eq!(machine.cpu.sound_timer, 59, "{} Hz", hz);
+
+        for _ in 0..59 {
+            machine.frame().unwrap();
+        }
+        assert_eq!(machine.cpu.delay_timer(), 0, "{} Hz", hz);
+        assert!(!machine.sound_active());
+    }
+}
+
+#[test]
+fn timers_stop_while_the_debugger_is_paused() {
+    let mut machine = timers_machine(600);
+    machine.frame().unwrap();
+    machine.debugger.pause();
+    for _ in 0..10 {
+        machine.frame().unwrap();
+    }
+    assert_eq!(machine.cpu.delay_timer(), 59);
+
+    // Stepping single instructions doesn't advance them either
+    machine.debugger.step(&machine.cpu);
+    machine.frame().unwrap();
+    assert_eq!(machine.cpu.delay_timer(), 59);
+
+    machine.debugger.resume(&machine.cpu);
+    machine.frame().unwrap();
+    assert_eq!(machine.cpu.delay_timer(), 58);
+}
+
+#[test]
+fn speed_steps_are_logarithmic_and_clamped() {
+    let mut cpu = Cpu::new(480, Quirks::vip());
+    assert_eq!(cpu.adjust_hz(4), 960);
+    assert_eq!(cpu.adjust_hz(-8), 240);
+    assert_eq!(cpu.adjust_hz(1), 285);
+    assert_eq!(cpu.adjust_hz(-1), 240);
+
+    assert_eq!(cpu.adjust_hz(-100), MIN_HZ);
+    assert_eq!(cpu.adjust_hz(-1), MIN_HZ);
+    assert_eq!(cpu.adjust_hz(1000), MAX_HZ);
+}